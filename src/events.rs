@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use super::*;
+
+/// Event log in JSON Lines format, one JSON object per line. Every executed game produces a "game" event
+/// followed by a "choice" event for each of its players. Games that were provided but could not fill
+/// their required roles produce an "unplayed_game" event. Game IDs are unique within the run.
+/// Games without roles only delay their consequent games, so they are not recorded.
+pub struct EventLog {
+    writer: BufWriter<File>,
+}
+
+impl EventLog {
+    pub fn new(pathname: &str) -> EventLog {
+        EventLog {writer: BufWriter::new(File::create(pathname).unwrap())}
+    }
+
+    /// Role-to-agent map is keyed by agent IDs, as multiplet roles have several players.
+    pub fn record_game(&mut self, tick: usize, game_id: usize, game: &Game, assigned_roles: &BTreeMap<AgentID, AnyRole>, chosen_choices: &BTreeMap<AgentID, Choice>, changes: &TileChanges) {
+        let roles = assigned_roles.iter()
+            .map(|(agent_id, role)| (agent_id.to_string(), json_string(&role.name())))
+            .collect::<Vec<(String, String)>>();
+        self.write_line(&[
+            ("event", json_string("game")),
+            ("tick", tick.to_string()),
+            ("game_id", game_id.to_string()),
+            ("game", json_string(&game.name)),
+            ("tile", game.tile.to_string()),
+            ("roles", json_object(&roles)),
+            ("treasury_deltas", json_resource_changes(&changes.tile)),
+        ]);
+
+        for (agent_id, choice) in chosen_choices {
+            let resource_changes = changes.agents.get(agent_id).cloned().unwrap_or_default();
+            self.write_line(&[
+                ("event", json_string("choice")),
+                ("tick", tick.to_string()),
+                ("game_id", game_id.to_string()),
+                ("agent_id", agent_id.to_string()),
+                ("role", json_string(&assigned_roles[agent_id].name())),
+                ("choice", json_string(&choice.name())),
+                ("resource_deltas", json_resource_changes(&resource_changes)),
+            ]);
+        }
+    }
+
+    pub fn record_unplayed_game(&mut self, tick: usize, game_id: usize, game: &Game) {
+        let roles = game.roles.keys()
+            .map(|role| json_string(&role.name()))
+            .collect::<Vec<String>>();
+        self.write_line(&[
+            ("event", json_string("unplayed_game")),
+            ("tick", tick.to_string()),
+            ("game_id", game_id.to_string()),
+            ("game", json_string(&game.name)),
+            ("tile", game.tile.to_string()),
+            ("roles", format!("[{}]", roles.join(","))),
+        ]);
+    }
+
+    pub fn flush(&mut self) {
+        self.writer.flush().unwrap();
+    }
+
+    fn write_line(&mut self, fields: &[(&str, String)]) {
+        let fields = fields.iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect::<Vec<(String, String)>>();
+        writeln!(self.writer, "{}", json_object(&fields)).unwrap();
+    }
+}
+
+/// Object of already serialized values.
+fn json_object(fields: &[(String, String)]) -> String {
+    let fields = fields.iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value))
+        .collect::<Vec<String>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            character if character.is_control() => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_resource_changes(changes: &ResourceChanges) -> String {
+    let fields = changes.iter()
+        .map(|(resource, change)| (resource.to_string(), change.to_string()))
+        .collect::<Vec<(String, String)>>();
+    json_object(&fields)
+}
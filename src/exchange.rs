@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+
+use super::*;
+
+/// Source tile (None for the reservoir), drainability, sharing, capacity and, for pools that are not shared,
+/// position of the game in the shuffled game pool. Equal keys always mean the same pool.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+struct PoolKey(Option<TileID>, AnyDrainability, AnySharing, Option<usize>, Option<usize>);
+
+/// Pool of agents that a role draws its players from (see players exchange in docs/runtime.md).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PoolDescription {
+    pub source: AnyAgentSource,
+    pub drainability: AnyDrainability,
+    pub sharing: AnySharing,
+    pub capacity: Option<usize>, // Pool holds at most this many randomly chosen agents of the source
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum AnyDrainability {
+    Drainable, // Assigned agents are removed from the pool, so every agent plays at most one game from it
+    NonDrainable, // Assigned agents stay in the pool and can be assigned to other games
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum AnySharing {
+    Shared, // One pool for all games of the tick that describe it the same way
+    PerGame, // Every game gets its own pool
+}
+
+/// Pools of agents populated before games of the tick are executed. Pools hold copies of agents
+/// as they were at the beginning of the tick, so only pre-existing information is used to build them.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerExchange {
+    pools: BTreeMap<PoolKey, Pool>,
+    reservoir_agents: BTreeMap<AgentID, Agent>, // Agents drawn from the reservoir into some pool during this tick
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Pool {
+    pub agents: Vec<Agent>,
+    pub last_assigned: Option<AgentID>, // Kept between ticks for round-robin assignment
+}
+
+impl PoolDescription {
+    /// Pool of all agents of the source, shared between games. Every agent plays at most one game from it per tick.
+    pub fn drainable(source: AnyAgentSource) -> PoolDescription {
+        PoolDescription {source, drainability: AnyDrainability::Drainable, sharing: AnySharing::Shared, capacity: None}
+    }
+
+    /// Pool of all agents of the source, shared between games. Agents can play any number of games from it.
+    pub fn non_drainable(source: AnyAgentSource) -> PoolDescription {
+        PoolDescription {source, drainability: AnyDrainability::NonDrainable, sharing: AnySharing::Shared, capacity: None}
+    }
+
+    /// Degenerate pool of a single randomly chosen agent that initiates the game. For tile sources the agent
+    /// is also drained from the shared drainable pool of the tile (see parent_pool_key).
+    pub fn initiator(source: AnyAgentSource) -> PoolDescription {
+        PoolDescription {source, drainability: AnyDrainability::Drainable, sharing: AnySharing::PerGame, capacity: Some(1)}
+    }
+
+    /// Returns the tile the agents come from, or None for the reservoir.
+    fn source_tile(&self, host_tile: TileID) -> Option<TileID> {
+        match self.source {
+            AnyAgentSource::Tile => Some(host_tile),
+            AnyAgentSource::OtherTile(tile) => Some(tile),
+            AnyAgentSource::Reservoir => None,
+        }
+    }
+}
+
+impl PlayerExchange {
+    /// Populates pools of all roles of all games. Tile pools are filled once with agents of the tile,
+    /// reservoir pools get freshly generated agents for every role connected to them.
+    pub fn populate(&mut self, games: &[Game], tiles: &[Tile], reservoir: &mut Reservoir, rng: &mut StdRng) {
+        for pool in self.pools.values_mut() {
+            pool.agents.clear();
+        }
+        self.reservoir_agents.clear();
+
+        let mut populated_pool_keys = BTreeSet::new();
+        for (game_index, game) in games.iter().enumerate() {
+            for description in game.roles.values() {
+                let pool = &description.pool;
+                let pool_key = PlayerExchange::pool_key(game_index, game, pool);
+                match pool.source_tile(game.tile) {
+                    Some(tile) => {
+                        let parent_pool_key = PlayerExchange::parent_pool_key(game, pool);
+                        for pool_key in std::iter::once(pool_key).chain(parent_pool_key) {
+                            if populated_pool_keys.insert(pool_key) { // Tile pools are populated once
+                                let PoolKey(_, _, _, capacity, _) = pool_key;
+                                let tile_agents = tiles[tile].agents.values().cloned();
+                                self.pools.entry(pool_key).or_default().agents = match capacity {
+                                    Some(capacity) => tile_agents.choose_multiple(rng, capacity),
+                                    None => tile_agents.collect(),
+                                };
+                            }
+                        }
+                    }
+                    None => {
+                        let agents = &mut self.pools.entry(pool_key).or_default().agents;
+                        let count = match description.uniqueness {
+                            AnyUniqueness::RequiredMultipletRole(min, max) |
+                            AnyUniqueness::OptionalMultipletRole(min, max) => if max == usize::MAX {min} else {max},
+                        };
+                        let count = pool.capacity.map_or(count, |capacity| usize::min(count, capacity.saturating_sub(agents.len())));
+                        for agent in reservoir.draw_agents(count, rng) {
+                            self.reservoir_agents.insert(agent.id, agent.clone());
+                            agents.push(agent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pool key depends only on the description of the pool and the tile it draws from.
+    /// Pools that are not shared also depend on the position of the game in the shuffled game pool.
+    fn pool_key(game_index: usize, game: &Game, pool: &PoolDescription) -> PoolKey {
+        let game_index = if pool.sharing == AnySharing::PerGame {Some(game_index)} else {None};
+        PoolKey(pool.source_tile(game.tile), pool.drainability, pool.sharing, pool.capacity, game_index)
+    }
+
+    /// Drainable tile pools that are not shared are drawn out of the shared drainable pool of the same tile,
+    /// so that their agents also play at most one game from the tile. Reservoir agents are drawn for a single role anyway.
+    fn parent_pool_key(game: &Game, pool: &PoolDescription) -> Option<PoolKey> {
+        let is_drawn_out_of_shared_pool = pool.drainability == AnyDrainability::Drainable
+            && pool.sharing == AnySharing::PerGame
+            && pool.source != AnyAgentSource::Reservoir;
+        is_drawn_out_of_shared_pool.then(|| PlayerExchange::pool_key(0, game, &PoolDescription::drainable(pool.source)))
+    }
+
+    /// Returns the shared drainable pool that the pool is drawn out of, if any (see parent_pool_key).
+    pub fn get_parent_pool_mut(&mut self, game: &Game, pool: &PoolDescription) -> Option<&mut Pool> {
+        self.pools.get_mut(&PlayerExchange::parent_pool_key(game, pool)?)
+    }
+
+    pub fn get_pool_mut(&mut self, game_index: usize, game: &Game, pool: &PoolDescription) -> &mut Pool {
+        self.pools.entry(PlayerExchange::pool_key(game_index, game, pool)).or_default()
+    }
+
+    /// Returns agent drawn from the reservoir during this tick, if the ID belongs to one.
+    pub fn get_reservoir_agent(&self, agent_id: AgentID) -> Option<&Agent> {
+        self.reservoir_agents.get(&agent_id)
+    }
+}
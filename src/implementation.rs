@@ -1,9 +1,13 @@
 use std::any::Any;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
 use std::hash::Hash;
 use std::iter::IntoIterator;
+use std::ops::RangeInclusive;
 use rand::distributions::Distribution;
-use rand::{Rng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use plotters::*;
@@ -12,6 +16,13 @@ use lazy_static::lazy_static;
 use std::any::TypeId;
 
 use super::*;
+use super::templates::read_game_templates;
+use super::providers::{ProviderSpec, read_provider_specs};
+use super::membership::get_members_stats;
+use super::exchange::AnyDrainability;
+use super::schedule::{AnyFrequency, Schedule};
+use super::market::AnySide;
+use super::memory::{ACTION_VALUES, CURRENT_GAME, LAST_CHOICE, LAST_UTILITY_CHANGE, AnyMemoryValue};
 
 trait ExtendedWith<T> {
     fn extended_with(self, new_element: T) -> Self;
@@ -24,56 +35,245 @@ impl<T> ExtendedWith<T> for Vec<T> {
     }
 }
 
-fn rng_decider(_tile: &Tile, _agent_id: AgentID, transient_actions: Vec<ActionFn>, _data: &DecisionAvailableData, rng: &mut StdRng) -> ActionFn {
-    let random_index = Uniform::new(0, transient_actions.len()).sample(rng);
-    transient_actions[random_index].clone()
+fn rng_decider(_tile: &Tile, _agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, _assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let random_index = Uniform::new(0, transient_choices.len()).sample(rng);
+    transient_choices[random_index].clone()
 }
 
-fn utility_decider(tile: &Tile, agent_id: AgentID, transient_actions: Vec<ActionFn>, _data: &DecisionAvailableData, rng: &mut StdRng) -> ActionFn {
-        let possible_future_utilities = transient_actions.iter()
-            .map(|action| (*action).clone())
-            .map(|f| {
-                let mut tile_clone = tile.clone();
-                f(&mut tile_clone, agent_id, rng);
-                tile.agents[agent_id].get_utility()
-            } )
-            .collect::<Vec<f64>>();
+/// Evaluates every action by Monte Carlo rollouts on clones of the tile, with rollouts and depth from decider parameters of the agent.
+fn utility_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, data: &DecisionAvailableData, memory: &mut Memory, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let params = tile.agents[&agent_id].decider_params;
+    let actions = transient_choices.iter()
+        .filter_map(|choice| if let Choice::Action(action) = choice {Some(action.clone())} else {None})
+        .collect::<Vec<Action>>();
+    if actions.is_empty() {
+        return rng_decider(tile, agent_id, transient_choices, data, memory, assigned_roles, rng); // Options depend on choices of other players, so they are not simulated
+    }
+
+    // Rollouts use a fork of the cloned stream, so the main stream is not advanced by the decision.
+    // All actions are evaluated with the same seeds, so differences between them are not hidden by noise.
+    let mut forked_rng = StdRng::from_rng(rng.clone()).unwrap();
+    let seeds = (0..params.lookahead_rollouts).map(|_| forked_rng.gen()).collect::<Vec<u64>>();
 
-        let choosen_index = possible_future_utilities.iter()
-            .enumerate()
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
-            .map(|(index, _)| index)
-            .unwrap();
+    let expected_utilities = actions.iter()
+        .map(|action| {
+            let total_utility = seeds.iter()
+                .map(|seed| {
+                    let mut rollout_rng = StdRng::seed_from_u64(*seed);
+                    let mut tile_clone = tile.clone();
+                    action.execute(&mut tile_clone, agent_id, &mut rollout_rng);
+                    for _ in 1..params.lookahead_depth {
+                        let next_action = &actions[rollout_rng.gen_range(0..actions.len())];
+                        next_action.execute(&mut tile_clone, agent_id, &mut rollout_rng);
+                    }
+                    tile_clone.agents[&agent_id].get_utility()
+                })
+                .sum::<f64>();
+            total_utility / seeds.len() as f64
+        })
+        .collect::<Vec<f64>>();
 
-        transient_actions[choosen_index].clone()
+    let choosen_index = expected_utilities.iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
+        .map(|(index, _)| index)
+        .unwrap();
+
+    Choice::Action(actions[choosen_index].clone())
 }
 
-fn trivial_participation_checker(_agent: &Agent, _game: &Game, _proposed_role: &AnyRole) -> bool {
+/// Epsilon-greedy multi-armed bandit. Values of choices in every game are learned across ticks from utility changes
+/// of the agent after the games (see learn). Choices that were never tried are tried first.
+fn bandit_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, memory: &mut Memory, _assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    if rng.gen_bool(tile.agents[&agent_id].decider_params.exploration_rate) {
+        let random_index = Uniform::new(0, transient_choices.len()).sample(rng);
+        return transient_choices[random_index].clone();
+    }
+
+    let Some(AnyMemoryValue::Text(game_name)) = memory.get(CURRENT_GAME) else {
+        panic!("Memory entry {} should hold the name of the game", CURRENT_GAME);
+    };
+    let Some(AnyMemoryValue::ActionValues(action_values)) = memory.get(ACTION_VALUES) else {
+        panic!("Memory entry {} should hold action values", ACTION_VALUES);
+    };
+    transient_choices.iter()
+        .max_by(|a, b| {
+            let value = |choice: &Choice| action_values.get(&get_action_key(game_name, &choice.name())).map_or(f64::INFINITY, |action_value| action_value.value);
+            value(a).total_cmp(&value(b)).then(std::cmp::Ordering::Greater) // Earlier choice wins ties
+        })
+        .unwrap()
+        .clone()
+}
+
+/// Action values are learned for every game separately, as the same choice can be worth differently in different games.
+fn get_action_key(game_name: &str, choice_name: &str) -> String {
+    format!("{}/{}", game_name, choice_name)
+}
+
+/// Memory agents start with. Learning agents start with empty action values, only agents that have them learn.
+fn get_initial_memory(decider_name: &str) -> Memory {
+    let mut memory = Memory::default();
+    if decider_name == "bandit" {
+        memory.insert(ACTION_VALUES, AnyMemoryValue::ActionValues(BTreeMap::new()));
+    }
+    memory
+}
+
+/// Moves the value of the choice of a learning agent in the game towards the observed reward.
+pub fn learn(agent: &mut Agent, game_name: &str, choice_name: &str, reward: f64) {
+    let learning_rate = agent.decider_params.learning_rate;
+    let Some(AnyMemoryValue::ActionValues(action_values)) = agent.memory.get_mut(ACTION_VALUES) else {
+        return; // Agent is not learning
+    };
+    let action_value = action_values.entry(get_action_key(game_name, choice_name)).or_default();
+    action_value.value = if action_value.updates == 0 {reward} else {action_value.value + learning_rate * (reward - action_value.value)};
+    action_value.updates += 1;
+}
+
+// Deciders below are classic strategies for collaborate/cheat games. They look at the opponents assigned
+// to the same game and at their history with these opponents. If collaborate or cheat options are
+// not available, choice is made by rng_decider.
+
+const GENEROSITY: f64 = 1.0 / 3.0; // Probability that generous tit-for-tat forgives cheating
+
+fn get_opponents(agent_id: AgentID, assigned_roles: &BTreeMap<AgentID, AnyRole>) -> Vec<AgentID> {
+    assigned_roles.keys().filter(|id| **id != agent_id).cloned().collect()
+}
+
+fn choose_option(transient_choices: &[Choice], option: &str) -> Option<Choice> {
+    transient_choices.iter()
+        .find(|choice| matches!(choice, Choice::Option(name) if name == option))
+        .cloned()
+}
+
+fn collaborate_or_cheat(should_collaborate: bool, transient_choices: Vec<Choice>, rng: &mut StdRng) -> Choice {
+    let option = if should_collaborate {COLLABORATE} else {CHEAT};
+    choose_option(&transient_choices, option).unwrap_or_else(|| {
+        let random_index = Uniform::new(0, transient_choices.len()).sample(rng);
+        transient_choices[random_index].clone()
+    })
+}
+
+/// Collaborates on the first move, then repeats the last move of every opponent.
+fn tit_for_tat_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let was_cheated = get_opponents(agent_id, assigned_roles).iter()
+        .filter_map(|opponent_id| tile.get_history(agent_id, *opponent_id).last())
+        .any(|interaction| interaction.opponent_option == CHEAT);
+    collaborate_or_cheat(!was_cheated, transient_choices, rng)
+}
+
+/// Collaborates until any opponent cheats once, then cheats against this opponent forever.
+fn grim_trigger_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let was_ever_cheated = get_opponents(agent_id, assigned_roles).iter()
+        .flat_map(|opponent_id| tile.get_history(agent_id, *opponent_id))
+        .any(|interaction| interaction.opponent_option == CHEAT);
+    collaborate_or_cheat(!was_ever_cheated, transient_choices, rng)
+}
+
+/// Same as tit-for-tat, but forgives cheating with probability of GENEROSITY.
+fn generous_tit_for_tat_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let was_cheated = get_opponents(agent_id, assigned_roles).iter()
+        .filter_map(|opponent_id| tile.get_history(agent_id, *opponent_id).last())
+        .any(|interaction| interaction.opponent_option == CHEAT);
+    let should_collaborate = !was_cheated || rng.gen_bool(GENEROSITY);
+    collaborate_or_cheat(should_collaborate, transient_choices, rng)
+}
+
+/// Win-stay, lose-shift. Repeats own last move if opponent collaborated, switches it if opponent cheated.
+fn pavlov_decider(tile: &Tile, agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    let last_interactions = get_opponents(agent_id, assigned_roles).iter()
+        .filter_map(|opponent_id| tile.get_history(agent_id, *opponent_id).last())
+        .cloned()
+        .collect::<Vec<Interaction>>();
+    let should_collaborate = last_interactions.iter().all(|interaction| {
+        let own_collaborated = interaction.own_option != CHEAT;
+        let opponent_cheated = interaction.opponent_option == CHEAT;
+        own_collaborated != opponent_cheated // Stay if won, shift if lost
+    });
+    collaborate_or_cheat(should_collaborate, transient_choices, rng)
+}
+
+/// Pavlov for any game: repeats the last choice if it did not decrease utility of the agent and is available again,
+/// otherwise makes a random choice. Uses the last game recorded in memory, whatever game it was.
+fn win_stay_lose_shift_decider(_tile: &Tile, _agent_id: AgentID, transient_choices: Vec<Choice>, _data: &DecisionAvailableData, memory: &mut Memory, _assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> Choice {
+    if let (Some(AnyMemoryValue::Text(last_choice)), Some(AnyMemoryValue::Float(last_utility_change))) = (memory.get(LAST_CHOICE), memory.get(LAST_UTILITY_CHANGE)) {
+        let same_choice = transient_choices.iter().find(|choice| choice.name() == *last_choice);
+        if let (Some(choice), true) = (same_choice, *last_utility_change >= 0.0) {
+            return choice.clone();
+        }
+    }
+    let random_index = Uniform::new(0, transient_choices.len()).sample(rng);
+    transient_choices[random_index].clone()
+}
+
+fn trivial_participation_checker(_agent: &Agent, _game: &Game, _proposed_role: &AnyRole, _reputations: &ReputationMatrix) -> bool {
     true
 }
 
-fn assign_and_consume_agents(game: &Game, available_agents: &mut Vec<Agent>) -> Option<BTreeMap<AgentID, AnyRole>> {
+/// Returns positions of candidates in the order they are offered roles, according to the assignment strategy of the game.
+fn order_candidates(candidates: &[Agent], assignment: AnyAssignment, last_assigned: Option<AgentID>, reputations: &ReputationMatrix, rng: &mut StdRng) -> Vec<usize> {
+    let mut order = (0..candidates.len()).collect::<Vec<usize>>();
+    match assignment {
+        AnyAssignment::UniformRandom => order.shuffle(rng),
+        AnyAssignment::WeightedByResources => {
+            let weights = candidates.iter()
+                .map(|agent| agent.resources.values().sum::<usize>() as f64 + 1.0) // Agents without resources can still be chosen
+                .collect::<Vec<f64>>();
+            shuffle_weighted(&mut order, &weights, rng);
+        }
+        AnyAssignment::WeightedByReputation => {
+            let candidate_ids = candidates.iter().map(|agent| agent.id).collect::<Vec<AgentID>>();
+            let averages = reputations.get_group_averages(&candidate_ids);
+            let weights = candidate_ids.iter().map(|agent_id| averages[agent_id]).collect::<Vec<f64>>();
+            shuffle_weighted(&mut order, &weights, rng);
+        }
+        AnyAssignment::RoundRobin => {
+            order.sort_by_key(|&position| {
+                let agent_id = candidates[position].id;
+                (last_assigned.is_some_and(|last_id| agent_id <= last_id), agent_id) // Agents after the last assigned one go first
+            });
+        }
+    }
+    order
+}
+
+/// Weighted random permutation, every next position is chosen with probability proportional to its weight.
+/// Every position gets key u^(1/weight) with uniform u, positions are sorted by keys in descending order.
+fn shuffle_weighted(order: &mut [usize], weights: &[f64], rng: &mut StdRng) {
+    let keys = weights.iter().map(|weight| rng.gen::<f64>().powf(1.0 / weight)).collect::<Vec<f64>>();
+    order.sort_by(|a, b| keys[*b].total_cmp(&keys[*a]));
+}
+
+fn assign_and_consume_agents(game: &Game, game_index: usize, exchange: &mut PlayerExchange, reputations: &ReputationMatrix, rng: &mut StdRng) -> Option<BTreeMap<AgentID, AnyRole>> {
     let mut assigned_agents: BTreeMap<AgentID, AnyRole> = BTreeMap::new();
     let all_roles = game.roles.clone().into_iter()
     .map(|(role, description)| {
         match description.uniqueness {
             AnyUniqueness::RequiredMultipletRole(min, max) =>
-            (AnyUniqueness::RequiredMultipletRole.type_id(), role as AnyRole, min, max),
+            (AnyUniqueness::RequiredMultipletRole.type_id(), role as AnyRole, min, max, description.pool),
             AnyUniqueness::OptionalMultipletRole(min, max) =>
-            (AnyUniqueness::OptionalMultipletRole.type_id(), role as AnyRole, min, max)
+            (AnyUniqueness::OptionalMultipletRole.type_id(), role as AnyRole, min, max, description.pool)
         }
     })
-    .collect::<Vec<(TypeId, AnyRole, usize, usize)>>();
+    .collect::<Vec<(TypeId, AnyRole, usize, usize, PoolDescription)>>();
 
-for (typeid, role, min_multiplicity, max_multiplicity) in all_roles.iter() {
-    assert!(*max_multiplicity > 0usize); // TODO: Move to the init phase?
-    assert!(max_multiplicity >= min_multiplicity); // TODO: Move to the init phase?
-    let mut multiplicity_remaining = max_multiplicity.clone();
+for (typeid, role, min_multiplicity, max_multiplicity, pool) in all_roles.iter() {
+    let mut multiplicity_remaining = *max_multiplicity;
     let mut agents_to_consume: Vec<AgentID> = vec![];
     let mut suggested_agents: BTreeMap<AgentID, AnyRole> = BTreeMap::new();
+    let parent_pool_agent_ids = exchange.get_parent_pool_mut(game, pool)
+        .map(|parent_pool| parent_pool.agents.iter().map(|agent| agent.id).collect::<BTreeSet<AgentID>>());
+    let candidate_pool = exchange.get_pool_mut(game_index, game, pool);
+    let order = order_candidates(&candidate_pool.agents, game.assignment, candidate_pool.last_assigned, reputations, rng);
 
-        'agent_loop: for agent in available_agents.iter() {
-            if (agent.participation_checker)(agent, game, role) {
+        'agent_loop: for agent in order.into_iter().map(|position| &candidate_pool.agents[position]) {
+            if assigned_agents.contains_key(&agent.id) || suggested_agents.contains_key(&agent.id) {
+                continue; // Agent can be in several pools of the game, but plays only one role
+            }
+            if parent_pool_agent_ids.as_ref().is_some_and(|agent_ids| !agent_ids.contains(&agent.id)) {
+                continue; // Agent was already drained from the shared pool by another game
+            }
+            if (agent.participation_checker)(agent, game, role, reputations) {
                 suggested_agents.insert(agent.id, role.to_owned());
                 agents_to_consume.push(agent.id);
 
@@ -84,7 +284,15 @@ for (typeid, role, min_multiplicity, max_multiplicity) in all_roles.iter() {
             }
         }
         if agents_to_consume.len() >= *min_multiplicity {
-            available_agents.retain(|agent| !agents_to_consume.contains(&agent.id));
+            if pool.drainability == AnyDrainability::Drainable {
+                candidate_pool.agents.retain(|agent| !agents_to_consume.contains(&agent.id));
+            }
+            if let Some(last_id) = agents_to_consume.last() {
+                candidate_pool.last_assigned = Some(*last_id);
+            }
+            if let Some(parent_pool) = exchange.get_parent_pool_mut(game, pool) {
+                parent_pool.agents.retain(|agent| !agents_to_consume.contains(&agent.id));
+            }
             assigned_agents.append(&mut suggested_agents);
         } else {
             if typeid == &AnyUniqueness::RequiredMultipletRole.type_id() {
@@ -96,48 +304,111 @@ for (typeid, role, min_multiplicity, max_multiplicity) in all_roles.iter() {
     Some(assigned_agents)
 }
 
-// How to add a new ActionFn to a Game:
-// 1) Write your ActionFn;
-// 2a) You can add your ActionFn to Agent initialization as one of the base_actions.
-// 2b) You can also use ActionFn in a transformer (for roles specified on game creation).
+// How to add a new Action to a Game:
+// 1) Write your ActionFn, it reads parameters of the action with action.param(name);
+// 2) Describe the action with its name and default parameters in lazy_static below and add it to get_known_actions;
+// 3a) You can add your Action to Agent initialization as one of the base_actions.
+// 3b) You can also use Action in a transformer (for roles specified on game creation).
 
-fn trivial_action(_tile: &mut Tile, _agent_id: AgentID, _rng: &mut StdRng) {} // Action that does nothing
+fn trivial_action(_tile: &mut Tile, _agent_id: AgentID, _action: &Action, _rng: &mut StdRng) {}
 
-fn mint_action(tile: &mut Tile, agent_id: AgentID, rng: &mut StdRng) {
-    let difficulty_growth_rate = 1.0001;
-    let probability_of_success = chance_to_mint_gold(&tile, difficulty_growth_rate);
+fn mint_action(tile: &mut Tile, agent_id: AgentID, action: &Action, rng: &mut StdRng) {
+    let probability_of_success = chance_to_mint_gold(tile, action.param("difficulty_growth_rate"));
 
     if rng.gen_bool(probability_of_success) {
-        *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) += 10;
+        *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0) += action.param("payout") as usize;
     }
 }
 
-fn work_action(tile: &mut Tile, agent_id: AgentID, _rng: &mut StdRng) {
-    *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) += 1;
+fn work_action(tile: &mut Tile, agent_id: AgentID, action: &Action, _rng: &mut StdRng) {
+    *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0) += action.param("payout") as usize;
 }
 
-fn play_lottery_action(tile: &mut Tile, agent_id: AgentID, _rng: &mut StdRng) {
-    let agent_resources = *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0);
-    if _rng.gen_bool(0.2) {
-        *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) = agent_resources * 2;
+fn play_lottery_action(tile: &mut Tile, agent_id: AgentID, action: &Action, rng: &mut StdRng) {
+    let agent_resources = *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0);
+    if rng.gen_bool(action.param("winning_chance")) {
+        *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0) = (agent_resources as f64 * action.param("multiplier")) as usize;
     } else {
-        *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) = 0;
+        *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0) = 0;
         *tile.resources.entry(AnyResource::Coins).or_insert(0) += agent_resources;
     }
 }
 
-
-fn pay_tax_action(tile: &mut Tile, agent_id: AgentID, _rng: &mut StdRng) {
-    let tax = *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) / 100;
-    *tile.agents[agent_id].resources.entry(AnyResource::Coins).or_insert(0) -= tax;
+fn pay_tax_action(tile: &mut Tile, agent_id: AgentID, action: &Action, _rng: &mut StdRng) {
+    let coins = *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0);
+    let tax = usize::min((coins as f64 * action.param("rate")) as usize, coins);
+    *tile.get_agent_mut(agent_id).resources.entry(AnyResource::Coins).or_insert(0) -= tax;
     *tile.resources.entry(AnyResource::Coins).or_insert(0) += tax;
 }
 
+/// Posts a bid for a random resource on the market of the tile. Market games should be sequential,
+/// as orders are not part of the joint changes of simultaneous games.
+fn bid_action(tile: &mut Tile, agent_id: AgentID, action: &Action, rng: &mut StdRng) {
+    let agent = tile.agents[&agent_id].clone();
+    tile.market.post_random_order(&agent, AnySide::Bid, action, rng);
+}
+
+fn ask_action(tile: &mut Tile, agent_id: AgentID, action: &Action, rng: &mut StdRng) {
+    let agent = tile.agents[&agent_id].clone();
+    tile.market.post_random_order(&agent, AnySide::Ask, action, rng);
+}
+
+lazy_static! {
+    static ref TRIVIAL_ACTION: Action = Action::new("trivial", "Does nothing", &[], trivial_action);
+    static ref MINT_ACTION: Action = Action::new(
+        "mint",
+        "Mints Coins with probability falling exponentially with total Coins of the tile",
+        &[("difficulty_growth_rate", 1.0001), ("payout", 10.0)],
+        mint_action,
+    );
+    static ref WORK_ACTION: Action = Action::new("work", "Earns Coins", &[("payout", 1.0)], work_action);
+    static ref PLAY_LOTTERY_ACTION: Action = Action::new(
+        "play_lottery",
+        "Bets all Coins. Multiplies them on win, otherwise gives them to the tile treasury",
+        &[("winning_chance", 0.2), ("multiplier", 2.0)],
+        play_lottery_action,
+    );
+    static ref PAY_TAX_ACTION: Action = Action::new("pay_tax", "Pays a share of Coins to the tile treasury", &[("rate", 0.01)], pay_tax_action);
+    // Initial price is the price of base resource in quote resource before the first trade (see Market::post_random_order)
+    static ref BID_ACTION: Action = Action::new(
+        "bid",
+        "Posts a bid for a random resource on the market of the tile",
+        &[("initial_price", 1.0), ("price_noise", 0.2), ("max_quantity", 5.0)],
+        bid_action,
+    );
+    static ref ASK_ACTION: Action = Action::new(
+        "ask",
+        "Posts an ask for a random resource on the market of the tile",
+        &[("initial_price", 1.0), ("price_noise", 0.2), ("max_quantity", 5.0)],
+        ask_action,
+    );
+}
+
+/// Returns all actions with their default parameters.
+pub fn get_known_actions() -> Vec<Action> {
+    [&*TRIVIAL_ACTION, &*MINT_ACTION, &*WORK_ACTION, &*PLAY_LOTTERY_ACTION, &*PAY_TAX_ACTION, &*BID_ACTION, &*ASK_ACTION]
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Returns valid values of the parameter of the action. Parameters that are not listed can take any value.
+pub fn get_param_range(action_name: &str, param: &str) -> RangeInclusive<f64> {
+    match (action_name, param) {
+        ("mint", "difficulty_growth_rate") => 1.0..=f64::INFINITY, // Lower rate makes chance to mint greater than 1
+        ("play_lottery", "winning_chance") | ("pay_tax", "rate") | (_, "price_noise") => 0.0..=1.0,
+        (_, "initial_price") => f64::MIN_POSITIVE..=f64::INFINITY,
+        (_, "max_quantity") => 1.0..=f64::INFINITY,
+        ("play_lottery", "multiplier") | (_, "payout") => 0.0..=f64::INFINITY, // Negative amounts would be saturated to 0
+        _ => f64::NEG_INFINITY..=f64::INFINITY,
+    }
+}
+
 fn chance_to_mint_gold(tile: &Tile, difficulty_growth_rate: f64) -> f64 {
     let agents_gold =tile.agents
-    .iter()
+    .values()
     .map(|agent|agent.resources.get(&AnyResource::Coins).unwrap_or(&0))
-    .fold(0usize, |acc, gold| acc + gold);
+    .sum::<usize>();
 
     let total_gold = agents_gold + tile.resources.get(&AnyResource::Coins).unwrap_or(&0);
 
@@ -145,17 +416,97 @@ fn chance_to_mint_gold(tile: &Tile, difficulty_growth_rate: f64) -> f64 {
     1.0/(initial_difficulty * f64::powi(difficulty_growth_rate, total_gold as i32))
 }
 
+const COLLABORATE: &str = "collaborate";
+const CHEAT: &str = "cheat";
+const DECLINE: &str = "decline";
+
+/// Basic two-agent game from docs/Basic.md. Both agents choose to collaborate or cheat at the same time,
+/// payoffs for 4 possible outcomes are taken from configs. Each agent changes its view of the opponent
+/// depending on whether the opponent collaborated or cheated.
+fn basic_two_agent_game(configs: &Configs) -> Game {
+    let first = AnyRole::TwoAgentRole(TwoAgentRole::First);
+    let second = AnyRole::TwoAgentRole(TwoAgentRole::Second);
+    let payoffs = [
+        (COLLABORATE, COLLABORATE, configs.both_collaborate_payoff, configs.both_collaborate_payoff),
+        (COLLABORATE, CHEAT, configs.cheated_payoff, configs.cheater_payoff),
+        (CHEAT, COLLABORATE, configs.cheater_payoff, configs.cheated_payoff),
+        (CHEAT, CHEAT, configs.both_cheat_payoff, configs.both_cheat_payoff),
+    ];
+
+    let mut outcomes: OutcomeMatrix = BTreeMap::new();
+    for (first_option, second_option, first_payoff, second_payoff) in payoffs {
+        let choices = BTreeMap::from([
+            (first.clone(), first_option.to_string()),
+            (second.clone(), second_option.to_string()),
+        ]);
+        let resource_changes = BTreeMap::from([
+            (first.clone(), BTreeMap::from([(AnyResource::Coins, first_payoff)])),
+            (second.clone(), BTreeMap::from([(AnyResource::Coins, second_payoff)])),
+        ]);
+        let reputation_change = |option| if option == CHEAT {configs.cheating_reputation_change} else {configs.collaboration_reputation_change};
+        let reputation_changes = BTreeMap::from([
+            (first.clone(), BTreeMap::from([(second.clone(), reputation_change(second_option))])),
+            (second.clone(), BTreeMap::from([(first.clone(), reputation_change(first_option))])),
+        ]);
+        outcomes.insert(choices, Outcome {resource_changes, reputation_changes});
+    }
+
+    let roles = [first, second].into_iter()
+        .map(|role| (role, RoleDescription {
+            uniqueness: AnyUniqueness::RequiredMultipletRole(1, 1),
+            transformer: |actions| actions,
+            options: vec![COLLABORATE.to_string(), CHEAT.to_string()],
+            pool: PoolDescription::drainable(AnyAgentSource::Tile),
+        }))
+        .collect();
+
+    Game {name: "basic_two_agent".to_string(), roles, consequent_game: None, outcomes: Some(outcomes), resolution: AnyResolution::Simultaneous, tile: 0, assignment: AnyAssignment::WeightedByReputation, action_params: BTreeMap::new()}
+}
+
+/// Declinable two-agent game from docs/Basic.md. Same as basic two-agent game, but both agents can also decline.
+/// If either agent declines, no resource change happens, so 5 distinct outcomes are possible.
+fn declinable_two_agent_game(configs: &Configs) -> Game {
+    let mut game = basic_two_agent_game(configs);
+    game.name = "declinable_two_agent".to_string();
+    for description in game.roles.values_mut() {
+        description.options.push(DECLINE.to_string());
+    }
+
+    let outcomes = game.outcomes.as_mut().unwrap();
+    let roles = game.roles.keys().cloned().collect::<Vec<AnyRole>>();
+    for first_option in [COLLABORATE, CHEAT, DECLINE] {
+        for second_option in [COLLABORATE, CHEAT, DECLINE] {
+            if first_option == DECLINE || second_option == DECLINE {
+                let choices = BTreeMap::from([
+                    (roles[0].clone(), first_option.to_string()),
+                    (roles[1].clone(), second_option.to_string()),
+                ]);
+                outcomes.insert(choices, Outcome::default());
+            }
+        }
+    }
+    game
+}
+
 lazy_static! {
     static ref THE_END_GAME: Game = {
         let role = AnyRole::TheEndRole(TheEndRole::Anyone);
         let description = RoleDescription {
             uniqueness: AnyUniqueness::RequiredMultipletRole(1, usize::MAX),
-            transformer: |_actions| {vec![pay_tax_action]},
+            transformer: |_actions| {vec![PAY_TAX_ACTION.clone()]},
+            options: vec![],
+            pool: PoolDescription::drainable(AnyAgentSource::Tile),
         };
 
         Game {
+            name: "the_end".to_string(),
             roles: BTreeMap::from([(role, description)]),
             consequent_game: None,
+            outcomes: None,
+            resolution: AnyResolution::Sequential,
+            tile: 0,
+            assignment: AnyAssignment::UniformRandom,
+            action_params: BTreeMap::new(),
         }
     };
 
@@ -163,12 +514,20 @@ lazy_static! {
         let role = AnyRole::LotteryRole(LotteryRole::Player);
         let description = RoleDescription {
             uniqueness: AnyUniqueness::RequiredMultipletRole(1, usize::MAX),
-            transformer: |actions| {actions.extended_with(play_lottery_action)},
+            transformer: |actions| {actions.extended_with(PLAY_LOTTERY_ACTION.clone())},
+            options: vec![],
+            pool: PoolDescription::non_drainable(AnyAgentSource::Tile), // Playing the lottery does not keep agents from other games
         };
 
         Game {
+            name: "lottery".to_string(),
             roles: BTreeMap::from([(role, description)]),
             consequent_game: None,
+            outcomes: None,
+            resolution: AnyResolution::Sequential,
+            tile: 0,
+            assignment: AnyAssignment::UniformRandom,
+            action_params: BTreeMap::new(),
         }
     };
 
@@ -178,7 +537,9 @@ lazy_static! {
             AnyRole::KingdomRole(KingdomRole::King),
             RoleDescription {
                 uniqueness: AnyUniqueness::RequiredMultipletRole(1usize, 1usize),
-                transformer: |actions| {actions.extended_with(mint_action)},
+                transformer: |actions| {actions.extended_with(MINT_ACTION.clone())},
+                options: vec![],
+                pool: PoolDescription::initiator(AnyAgentSource::Tile), // Kingdom is initiated by a single agent
             }
         );
 
@@ -186,100 +547,357 @@ lazy_static! {
             AnyRole::KingdomRole(KingdomRole::Peasant),
             RoleDescription {
                 uniqueness: AnyUniqueness::OptionalMultipletRole(0usize, usize::MAX),
-                transformer: |actions| {actions.extended_with(work_action)},
+                transformer: |actions| {actions.extended_with(WORK_ACTION.clone())},
+                options: vec![],
+                pool: PoolDescription::drainable(AnyAgentSource::Tile),
             }
         );
 
         let consequent_game = Some(Box::from(Game::create_delayed_consequent_game(30, THE_END_GAME.clone())));
-        Game {roles, consequent_game}
+        Game {name: "kingdom".to_string(), roles, consequent_game, outcomes: None, resolution: AnyResolution::Sequential, tile: 0, assignment: AnyAssignment::WeightedByResources, action_params: BTreeMap::new()}
     };
+
+    static ref MARKET_GAME: Game = {
+        let role = AnyRole::MarketRole(MarketRole::Trader);
+        let description = RoleDescription {
+            uniqueness: AnyUniqueness::OptionalMultipletRole(2, 20),
+            transformer: |actions| {actions.extended_with(BID_ACTION.clone()).extended_with(ASK_ACTION.clone())},
+            options: vec![],
+            pool: PoolDescription::non_drainable(AnyAgentSource::Tile), // Trading does not keep agents from other games
+        };
+
+        Game {
+            name: "market".to_string(),
+            roles: BTreeMap::from([(role, description)]),
+            consequent_game: None,
+            outcomes: None,
+            resolution: AnyResolution::Sequential,
+            tile: 0,
+            assignment: AnyAssignment::UniformRandom,
+            action_params: BTreeMap::new(),
+        }
+    };
+
+    static ref GAME_TEMPLATES: BTreeMap<String, Game> = read_game_templates("games");
+    static ref PROVIDER_SPECS: Vec<ProviderSpec> = read_provider_specs("providers");
 }
 
 #[derive(Clone)]
-struct KingdomGameProvider;
+struct KingdomGameProvider {
+    tile: TileID,
+}
 impl GameProvider for KingdomGameProvider {
     fn provide_game(&self) -> Game {
-        let game = KINGDOM_GAME.clone();
-        self.check_if_all_roles_are_described(&game.roles).unwrap(); // TODO: Move checks outside impl, check consequent games.
-        game
+        KINGDOM_GAME.clone().on_tile(self.tile)
     }
 
-    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), String> {
+    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), GameValidationError> {
         for role in KingdomRole::iter() {
             if !roles.contains_key(&AnyRole::KingdomRole(role.clone())) {
-                let e = format!("No description for this role: {:?}", &role);
-                return Err(e);
+                return Err(GameValidationError::MissingRoleDescription(AnyRole::KingdomRole(role)));
             }
         }
         Ok(())
     }
 }
 
-struct LotteryGameProvider;
+struct LotteryGameProvider {
+    tile: TileID,
+}
 impl GameProvider for LotteryGameProvider {
     fn provide_game(&self) -> Game {
-        let game = LOTTERY.clone();
-        self.check_if_all_roles_are_described(&game.roles).unwrap(); // TODO: Move checks outside impl, check consequent games.
-        game
+        LOTTERY.clone().on_tile(self.tile)
     }
 
 
-    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), String> {
+    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), GameValidationError> {
         for role in LotteryRole::iter() {
             if !roles.contains_key(&AnyRole::LotteryRole(role.clone())) {
-                let e = format!("No description for this role: {:?}", &role);
-                return Err(e);
+                return Err(GameValidationError::MissingRoleDescription(AnyRole::LotteryRole(role)));
             }
         }
         Ok(())
     }
 }
 
-fn initialize_agents(configs: &Configs) -> Vec<Agent> {
+/// Agents post bids and asks on the market of the tile, orders are matched at the end of the tick.
+struct MarketGameProvider {
+    tile: TileID,
+}
+impl GameProvider for MarketGameProvider {
+    fn provide_game(&self) -> Game {
+        MARKET_GAME.clone().on_tile(self.tile)
+    }
+}
+
+struct BasicTwoAgentGameProvider<'a> {
+    configs: &'a Configs,
+    tile: TileID,
+}
+impl GameProvider for BasicTwoAgentGameProvider<'_> {
+    fn provide_game(&self) -> Game {
+        basic_two_agent_game(self.configs).on_tile(self.tile)
+    }
+}
+
+struct DeclinableTwoAgentGameProvider<'a> {
+    configs: &'a Configs,
+    tile: TileID,
+}
+impl GameProvider for DeclinableTwoAgentGameProvider<'_> {
+    fn provide_game(&self) -> Game {
+        declinable_two_agent_game(self.configs).on_tile(self.tile)
+    }
+}
+
+/// Provider built from a spec. Provides games of the base provider with pools of roles overridden by the spec.
+struct SpecGameProvider<'a> {
+    base: Box<dyn GameProvider + 'a>,
+    pools: &'static BTreeMap<String, PoolDescription>,
+    action_params: &'static BTreeMap<String, ActionParams>,
+    configs: &'a Configs,
+}
+impl GameProvider for SpecGameProvider<'_> {
+    /// Action parameters of the spec override action parameters from configs.
+    fn provide_game(&self) -> Game {
+        let mut game = self.base.provide_game()
+            .with_action_params(&self.configs.action_params)
+            .with_action_params(self.action_params);
+        for (role, description) in game.roles.iter_mut() {
+            if let Some(pool) = self.pools.get(&role.name()) {
+                description.pool = *pool;
+            }
+        }
+        game
+    }
+
+    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), GameValidationError> {
+        for name in self.pools.keys() {
+            if !roles.keys().any(|role| &role.name() == name) {
+                return Err(GameValidationError::UnknownRole(name.clone()));
+            }
+        }
+        self.base.check_if_all_roles_are_described(roles)
+    }
+}
+
+struct TemplateGameProvider {
+    template: &'static Game,
+    tile: TileID,
+}
+impl GameProvider for TemplateGameProvider {
+    fn provide_game(&self) -> Game {
+        self.template.clone().on_tile(self.tile)
+    }
+}
+
+/// Returns the decider together with the memory its agents start with.
+fn get_decider(name: &str) -> (DeciderFn, Memory) {
+    let decider: DeciderFn = match name {
+        "rng" => rng_decider,
+        "utility" => utility_decider,
+        "tit_for_tat" => tit_for_tat_decider,
+        "grim_trigger" => grim_trigger_decider,
+        "generous_tit_for_tat" => generous_tit_for_tat_decider,
+        "pavlov" => pavlov_decider,
+        "bandit" => bandit_decider,
+        "win_stay_lose_shift" => win_stay_lose_shift_decider,
+        _ => panic!("Unknown decider: {}", name),
+    };
+    (decider, get_initial_memory(name))
+}
+
+fn initialize_agents(configs: &TileConfigs, decider_params: DeciderParams, first_id: AgentID) -> Vec<Agent> {
     let mut agents = vec![];
 
-    let border_index = configs.agent_count / 10;
-    for i in 0..configs.agent_count {
-        let decider = if i < border_index {
-            utility_decider
-        } else {
-            rng_decider
-        };
+    let mut deciders = configs.deciders.iter()
+        .flat_map(|(name, count)| std::iter::repeat_n(get_decider(name), *count));
+    let mut utilities = configs.utilities.iter()
+        .flat_map(|(utility, count)| std::iter::repeat_n(utility.clone(), *count));
+    for i in first_id..first_id + configs.agent_count {
+        let (decider, memory) = deciders.next().unwrap_or((rng_decider, Memory::default())); // Agents without configured decider use rng_decider
+        let utility = utilities.next().unwrap_or_default(); // Agents without configured utility use log utility
 
-        agents.push(
-            Agent::new(
-                BTreeMap::new(),
-                vec![trivial_action],
-                decider,
-                trivial_participation_checker,
-                i as AgentID,
-            )
+        let mut agent = Agent::new(
+            BTreeMap::new(),
+            vec![TRIVIAL_ACTION.clone()],
+            decider,
+            decider_params,
+            utility,
+            trivial_participation_checker,
+            i as AgentID,
         );
+        agent.memory = memory;
+        agents.push(agent);
     }
 
     agents
 }
 
-fn provide_all_games(gamepool: &mut Vec<Game>, tick: usize) -> () {
-    if tick % 3 == 0 {
-        gamepool.push(KingdomGameProvider.provide_game());
+fn initialize_reservoir(configs: &Configs) -> Reservoir {
+    let deciders = configs.reservoir_deciders.iter()
+        .map(|(name, weight)| (get_decider(name), *weight))
+        .collect();
+    let first_id = configs.tiles.iter().map(|tile| tile.agent_count).sum(); // Reservoir agents get IDs after agents of all tiles
+    Reservoir::new(&configs.reservoir_resources, deciders, configs.decider_params, configs.reservoir_utility.clone(), vec![TRIVIAL_ACTION.clone()], trivial_participation_checker, first_id)
+}
+
+const MIN_PARTICIPATIONS_FOR_MEMBERSHIP_DECISION: usize = 10; // Members do not leave until they have played this many games
+
+/// Members always stay and nobody joins.
+fn closed_membership_rule(agent: &Agent, tile: &Tile, _baseline: &ParticipationStats) -> bool {
+    tile.agents.contains_key(&agent.id)
+}
+
+/// Members leave if they do worse than the reservoir baseline. Former members return only if they did not do worse
+/// than it while being members. Other candidates join if members on average do better than the baseline,
+/// or if members have too few games to tell (so that an emptied tile is refilled).
+fn baseline_membership_rule(agent: &Agent, tile: &Tile, baseline: &ParticipationStats) -> bool {
+    let own_stats = tile.memberships.get(&agent.id).map(|record| &record.stats);
+    let has_own_record = own_stats.is_some_and(|stats| stats.participations() >= MIN_PARTICIPATIONS_FOR_MEMBERSHIP_DECISION);
+    if tile.agents.contains_key(&agent.id) || has_own_record {
+        let stats = own_stats.unwrap();
+        stats.participations() < MIN_PARTICIPATIONS_FOR_MEMBERSHIP_DECISION
+            || stats.average_utility_change() >= baseline.average_utility_change()
+    } else {
+        let members_stats = get_members_stats(tile);
+        members_stats.participations() < MIN_PARTICIPATIONS_FOR_MEMBERSHIP_DECISION
+            || members_stats.average_utility_change() > baseline.average_utility_change()
+    }
+}
+
+pub fn get_membership_rule(name: &str) -> MembershipRuleFn {
+    match name {
+        "closed" => closed_membership_rule,
+        "baseline" => baseline_membership_rule,
+        _ => panic!("Unknown membership rule: {}", name),
     }
-    if tick % 50 == 0 {
-        gamepool.push(LotteryGameProvider.provide_game());
+}
+
+fn get_base_game_provider<'a>(game: &str, configs: &'a Configs, tile: TileID) -> Option<Box<dyn GameProvider + 'a>> {
+    match game {
+        "kingdom" => Some(Box::new(KingdomGameProvider {tile})),
+        "lottery" => Some(Box::new(LotteryGameProvider {tile})),
+        "basic_two_agent" => Some(Box::new(BasicTwoAgentGameProvider {configs, tile})),
+        "declinable_two_agent" => Some(Box::new(DeclinableTwoAgentGameProvider {configs, tile})),
+        "market" => Some(Box::new(MarketGameProvider {tile})),
+        _ => GAME_TEMPLATES.get(game).map(|template| Box::new(TemplateGameProvider {template, tile}) as Box<dyn GameProvider>),
     }
 }
 
+/// Every game provider built from provider specs, with the frequency of its games.
+fn get_game_providers(configs: &Configs) -> Vec<(AnyFrequency, Box<dyn GameProvider + '_>)> {
+    let mut providers: Vec<(AnyFrequency, Box<dyn GameProvider>)> = vec![];
+    for spec in PROVIDER_SPECS.iter() {
+        let tiles = spec.tiles.clone().unwrap_or_else(|| (0..configs.tiles.len()).collect());
+        for tile in tiles {
+            let base = get_base_game_provider(&spec.game, configs, tile).unwrap_or_else(|| panic!("Unknown game: {}", spec.game));
+            providers.push((spec.frequency, Box::new(SpecGameProvider {base, pools: &spec.pools, action_params: &spec.action_params, configs})));
+        }
+    }
+    providers
+}
+
+/// Builds every game provider once, together with its schedule.
+fn initialize_providers<'a>(configs: &'a Configs, rng: &mut StdRng) -> ScheduledProviders<'a> {
+    get_game_providers(configs).into_iter()
+        .map(|(frequency, provider)| (provider, Schedule::new(frequency, rng)))
+        .collect()
+}
+
+fn provide_all_games(gamepool: &mut Vec<Game>, providers: &mut ScheduledProviders, tick: usize, rng: &mut StdRng) {
+    for (provider, schedule) in providers.iter_mut() {
+        for _ in 0..schedule.count_games(tick, rng) {
+            gamepool.push(provider.provide_game());
+        }
+    }
+}
+
+fn validate_all_games(configs: &Configs) -> Result<(), GameValidationError> {
+    for spec in PROVIDER_SPECS.iter() {
+        if get_base_game_provider(&spec.game, configs, 0).is_none() {
+            return Err(GameValidationError::UnknownGame(spec.game.clone()));
+        }
+        let other_tiles = spec.pools.values().filter_map(|pool| match pool.source {
+            AnyAgentSource::OtherTile(tile) => Some(tile),
+            _ => None,
+        });
+        for tile in spec.tiles.iter().flatten().copied().chain(other_tiles) {
+            if tile >= configs.tiles.len() {
+                return Err(GameValidationError::UnknownTile(tile));
+            }
+        }
+    }
+
+    for (_, provider) in get_game_providers(configs) {
+        provider.validate()?;
+    }
+    Ok(())
+}
+
 /// Use get_* functions to pass trait-implementing-structs to the main fn.
 
-#[derive(PartialEq, PartialOrd, Eq, Ord, Debug, Hash, Clone, EnumIter)]
+#[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Clone)]
 pub enum AnyResource {
     Coins,
+    Defined(String), // Resources defined in config
+}
+
+static DEFINED_RESOURCES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Defines resources in addition to Coins. Called once after configs are read, before any resource is used.
+pub fn define_resources(names: &[String]) {
+    let names = names.iter().filter(|name| *name != "Coins").cloned().collect();
+    DEFINED_RESOURCES.set(names).expect("Resources are already defined");
+}
+
+/// Defines Food in addition to Coins for tests, which share the definition as they run in one process.
+#[cfg(test)]
+pub fn define_test_resources() {
+    DEFINED_RESOURCES.get_or_init(|| vec!["Food".to_string()]);
+}
+
+impl AnyResource {
+    /// Iterates over Coins and all resources defined in config.
+    pub fn iter() -> impl Iterator<Item = AnyResource> {
+        let defined = DEFINED_RESOURCES.get().expect("Resources should be defined before they are used");
+        std::iter::once(AnyResource::Coins).chain(defined.iter().cloned().map(AnyResource::Defined))
+    }
+}
+
+impl FromStr for AnyResource {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<AnyResource, String> {
+        AnyResource::iter()
+            .find(|resource| resource.to_string() == name)
+            .ok_or(format!("Unknown resource: {}", name))
+    }
+}
+
+impl fmt::Display for AnyResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnyResource::Coins => write!(f, "Coins"),
+            AnyResource::Defined(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Debug for AnyResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f) // Resources are printed by name in logs
+    }
 }
 
 pub fn get_initializer() -> AgentInitializerFn {
     initialize_agents
 }
 
+pub fn get_reservoir() -> ReservoirInitializerFn {
+    initialize_reservoir
+}
+
 pub fn get_agent_assigner() -> AgentAssignerFn {
     assign_and_consume_agents
 }
@@ -288,11 +906,50 @@ pub fn get_pool_provider() -> PoolProviderFn {
     provide_all_games
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub fn get_provider_initializer() -> ProviderInitializerFn {
+    initialize_providers
+}
+
+pub fn get_game_validator() -> GameValidatorFn {
+    validate_all_games
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub enum AnyRole {
     KingdomRole(KingdomRole),
     TheEndRole(TheEndRole),
     LotteryRole(LotteryRole),
+    TwoAgentRole(TwoAgentRole),
+    MarketRole(MarketRole),
+    TemplateRole(String), // Roles of games loaded from templates are named in TOML
+}
+
+impl AnyRole {
+    /// Name of the role in specs: variant of the role enum, or name of the template role.
+    pub fn name(&self) -> String {
+        match self {
+            AnyRole::KingdomRole(role) => format!("{:?}", role),
+            AnyRole::TheEndRole(role) => format!("{:?}", role),
+            AnyRole::LotteryRole(role) => format!("{:?}", role),
+            AnyRole::TwoAgentRole(role) => format!("{:?}", role),
+            AnyRole::MarketRole(role) => format!("{:?}", role),
+            AnyRole::TemplateRole(name) => name.clone(),
+        }
+    }
+
+    /// All roles of the same role enum. A game that describes one of them should describe all of them.
+    /// Template roles have none, as they are checked against the outcome matrix of their game.
+    pub fn siblings(&self) -> Vec<AnyRole> {
+        match self {
+            AnyRole::KingdomRole(_) => KingdomRole::iter().map(AnyRole::KingdomRole).collect(),
+            AnyRole::TheEndRole(_) => TheEndRole::iter().map(AnyRole::TheEndRole).collect(),
+            AnyRole::LotteryRole(_) => LotteryRole::iter().map(AnyRole::LotteryRole).collect(),
+            AnyRole::TwoAgentRole(_) => TwoAgentRole::iter().map(AnyRole::TwoAgentRole).collect(),
+            AnyRole::MarketRole(_) => MarketRole::iter().map(AnyRole::MarketRole).collect(),
+            AnyRole::TemplateRole(_) => vec![],
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, EnumIter, Debug)]
@@ -310,3 +967,122 @@ pub enum TheEndRole {
 pub enum LotteryRole {
     Player
 }
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, EnumIter, Debug)]
+pub enum TwoAgentRole {
+    First,
+    Second,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, EnumIter, Debug)]
+pub enum MarketRole {
+    Trader,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECIDER_PARAMS: DeciderParams = DeciderParams {lookahead_rollouts: 4, lookahead_depth: 1, exploration_rate: 0.0, learning_rate: 0.0};
+
+    /// Agents with IDs from 0 holding given amounts of Coins.
+    fn get_agents(coins: &[usize]) -> Vec<Agent> {
+        define_test_resources();
+        coins.iter().enumerate()
+            .map(|(id, coins)| {
+                let resources = BTreeMap::from([(AnyResource::Coins, *coins)]);
+                Agent::new(resources, vec![TRIVIAL_ACTION.clone()], rng_decider, DECIDER_PARAMS, UtilityFunction::default(), trivial_participation_checker, id)
+            })
+            .collect()
+    }
+
+    fn get_tile(coins: &[usize]) -> Tile {
+        Tile::new(get_agents(coins), BTreeMap::new(), ReputationMatrix::new(1.0), closed_membership_rule)
+    }
+
+    fn get_reservoir() -> Reservoir {
+        Reservoir::new(&BTreeMap::new(), vec![((rng_decider, Memory::default()), 1.0)], DECIDER_PARAMS, UtilityFunction::default(), vec![], trivial_participation_checker, 100)
+    }
+
+    #[test]
+    fn utility_decider_prefers_action_that_raises_utility() {
+        let tile = get_tile(&[10, 10]);
+        let choices = vec![Choice::Action(WORK_ACTION.clone()), Choice::Action(TRIVIAL_ACTION.clone())]; // Ties would pick the last one
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut untouched_rng = rng.clone();
+
+        let choice = utility_decider(&tile, 0, choices, &BTreeMap::new(), &mut Memory::default(), &BTreeMap::new(), &mut rng);
+        assert_eq!(choice.name(), "work");
+        assert_eq!(rng.gen::<u64>(), untouched_rng.gen::<u64>()); // Rollouts do not advance the stream of the caller
+    }
+
+    #[test]
+    fn action_params_out_of_range_are_rejected() {
+        let with_param = |action: &str, param: &str, value: f64| {
+            let params = BTreeMap::from([(action.to_string(), BTreeMap::from([(param.to_string(), value)]))]);
+            KINGDOM_GAME.clone().with_action_params(&params).validate()
+        };
+
+        assert!(with_param("play_lottery", "winning_chance", 1.0).is_ok());
+        assert!(with_param("mint", "difficulty_growth_rate", 1.0001).is_ok());
+        for (action, param, value) in [("play_lottery", "winning_chance", 1.5), ("mint", "difficulty_growth_rate", 0.9), ("work", "payout", -1.0), ("pay_tax", "rate", f64::NAN)] {
+            assert!(matches!(with_param(action, param, value), Err(GameValidationError::InvalidActionParam(..))), "{} of {} should be rejected", param, action);
+        }
+    }
+
+    fn get_ordered_ids(candidates: &[Agent], assignment: AnyAssignment, last_assigned: Option<AgentID>, reputations: &ReputationMatrix, seed: u64) -> Vec<AgentID> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        order_candidates(candidates, assignment, last_assigned, reputations, &mut rng).into_iter()
+            .map(|position| candidates[position].id)
+            .collect()
+    }
+
+    #[test]
+    fn round_robin_continues_after_last_assigned_agent() {
+        let candidates = get_agents(&[0; 5]);
+        let reputations = ReputationMatrix::new(1.0);
+        assert_eq!(get_ordered_ids(&candidates, AnyAssignment::RoundRobin, None, &reputations, 0), vec![0, 1, 2, 3, 4]);
+        assert_eq!(get_ordered_ids(&candidates, AnyAssignment::RoundRobin, Some(2), &reputations, 0), vec![3, 4, 0, 1, 2]);
+        assert_eq!(get_ordered_ids(&candidates, AnyAssignment::RoundRobin, Some(4), &reputations, 0), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn random_orders_are_reproducible_with_the_same_seed() {
+        let candidates = get_agents(&[0, 5, 10, 20, 40, 80]);
+        let reputations = ReputationMatrix::new(1.0);
+        for assignment in [AnyAssignment::UniformRandom, AnyAssignment::WeightedByResources, AnyAssignment::WeightedByReputation] {
+            let order = get_ordered_ids(&candidates, assignment, None, &reputations, 7);
+            assert_eq!(order, get_ordered_ids(&candidates, assignment, None, &reputations, 7));
+            let mut sorted_order = order.clone();
+            sorted_order.sort();
+            assert_eq!(sorted_order, vec![0, 1, 2, 3, 4, 5], "{:?} should order every candidate once", assignment);
+        }
+    }
+
+    #[test]
+    fn candidates_with_zero_reputation_are_offered_roles_last() {
+        let candidates = get_agents(&[0; 4]);
+        let mut reputations = ReputationMatrix::new(1.0);
+        for observer in 1..4 {
+            reputations.change(observer, 0, -2.0); // Reputation is clamped to 0
+        }
+        for seed in 0..20 {
+            assert_eq!(get_ordered_ids(&candidates, AnyAssignment::WeightedByReputation, None, &reputations, seed).last(), Some(&0));
+        }
+    }
+
+    #[test]
+    fn initiator_is_drained_from_shared_pool() {
+        let tiles = vec![get_tile(&[10; 5])];
+        let games = vec![KINGDOM_GAME.clone(), KINGDOM_GAME.clone()];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut exchange = PlayerExchange::default();
+        exchange.populate(&games, &tiles, &mut get_reservoir(), &mut rng);
+
+        let first = assign_and_consume_agents(&games[0], 0, &mut exchange, &tiles[0].reputations, &mut rng).unwrap();
+        assert_eq!(first.len(), 5); // King and all other agents as peasants
+        assert_eq!(first.values().filter(|role| **role == AnyRole::KingdomRole(KingdomRole::King)).count(), 1);
+        // King of the second kingdom can only be an agent that has not played yet, and there is none
+        assert!(assign_and_consume_agents(&games[1], 1, &mut exchange, &tiles[0].reputations, &mut rng).is_none());
+    }
+}
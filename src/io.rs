@@ -1,4 +1,6 @@
 use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::vec;
 use std::iter::IntoIterator;
 use plotters::{coord::Shift, prelude::*};
@@ -6,65 +8,266 @@ use toml::Value;
 use std::path::PathBuf;
 use walkdir::WalkDir;
 use std::cmp::min;
+use std::collections::BTreeMap;
 
-use super::{AnyResource, Tile};
+use super::{ActionParams, AgentID, AnyResource, AnyUtility, DeciderParams, Tile, UtilityFunction};
+use super::templates::read_action_params;
 
 #[derive(Debug, Clone)]
 pub struct Configs { 
     pub plot_graph: bool, 
     pub plotting_frame_subselection_factor: usize, 
+    pub write_time_series: bool,
+    pub time_series_subselection_factor: usize, // Time series are written every this many ticks
+    pub write_event_log: bool,
+    pub action_params: BTreeMap<String, ActionParams>, // Action names mapped to their parameters, override defaults of all games
     pub tick_count: usize, 
-    pub agent_count: usize,
     pub seed: usize,
+    pub both_collaborate_payoff: i64, // Payoffs of the basic two-agent game
+    pub cheater_payoff: i64,
+    pub cheated_payoff: i64,
+    pub both_cheat_payoff: i64,
+    pub collaboration_reputation_change: f64, // Change of the view of the opponent who collaborated
+    pub cheating_reputation_change: f64, // Change of the view of the opponent who cheated
+    pub resources: Vec<String>, // Resources in addition to Coins, defined before the simulation starts
+    pub reservoir_resources: BTreeMap<AnyResource, (usize, usize)>, // Ranges of uniformly distributed initial resources of reservoir agents
+    pub reservoir_deciders: Vec<(String, f64)>, // Decider names and their weights among reservoir agents
+    pub reservoir_utility: UtilityFunction,
+    pub decider_params: DeciderParams,
+    pub tiles: Vec<TileConfigs>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TileConfigs {
+    pub agent_count: usize,
+    pub deciders: Vec<(String, usize)>, // Decider names and number of agents using them, assigned in order of agent IDs
+    pub utilities: Vec<(UtilityFunction, usize)>, // Utility functions and number of agents using them, assigned in order of agent IDs
+    pub membership_rule: String,
+    pub membership_candidate_count: usize, // Number of reservoir agents considering to join the tile every tick
 }
 
 fn try_to_read_integer(entry: &Value, searched_var: &str) -> usize {
-    let value = entry.get(searched_var).expect(&format!("{} variable not found", searched_var));
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
     let extracted_value = value.as_integer().unwrap();
     extracted_value as usize
 }
 
-pub fn read_configs() -> Configs {
-    let toml_files: Vec<PathBuf> = WalkDir::new(".")
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|entry| 
-            entry.file_type().is_file() &&
-            entry.file_name().to_string_lossy().ends_with(".toml"))
-        .map(|entry| entry.into_path() )
-        .collect();
+fn try_to_read_signed_integer(entry: &Value, searched_var: &str) -> i64 {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_integer().unwrap()
+}
+
+/// Integer values are read as floats, so that 1 can be written instead of 1.0.
+fn try_to_read_float(entry: &Value, searched_var: &str) -> f64 {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_float()
+        .or(value.as_integer().map(|value| value as f64))
+        .unwrap_or_else(|| panic!("{} variable should be a number", searched_var))
+}
+
+fn try_to_read_deciders(entry: &Value, searched_var: &str) -> Vec<(String, usize)> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_array().unwrap()
+        .iter()
+        .map(|decider| (decider.get("name").unwrap().as_str().unwrap().to_string(), try_to_read_integer(decider, "count")))
+        .collect()
+}
+
+/// Resources are read by names from the resource list of the config, as they are defined only after configs are read.
+fn read_resource(name: &str, resources: &[String]) -> AnyResource {
+    if name == "Coins" {
+        AnyResource::Coins
+    } else if resources.iter().any(|resource| resource == name) {
+        AnyResource::Defined(name.to_string())
+    } else {
+        panic!("Unknown resource: {}", name)
+    }
+}
+
+fn try_to_read_resource_ranges(entry: &Value, searched_var: &str, resources: &[String]) -> BTreeMap<AnyResource, (usize, usize)> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_table().unwrap()
+        .iter()
+        .map(|(resource, range)| {
+            let resource = read_resource(resource, resources);
+            let range = range.as_array().unwrap();
+            (resource, (range[0].as_integer().unwrap() as usize, range[1].as_integer().unwrap() as usize))
+        })
+        .collect()
+}
+
+fn try_to_read_weighted_deciders(entry: &Value, searched_var: &str) -> Vec<(String, f64)> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_array().unwrap()
+        .iter()
+        .map(|decider| (decider.get("name").unwrap().as_str().unwrap().to_string(), try_to_read_float(decider, "weight")))
+        .collect()
+}
+
+fn try_to_read_string(entry: &Value, searched_var: &str) -> String {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_str().unwrap().to_string()
+}
+
+fn try_to_read_tiles(entry: &Value, searched_var: &str, resources: &[String]) -> Vec<TileConfigs> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_array().unwrap()
+        .iter()
+        .map(|tile| TileConfigs {
+            agent_count: try_to_read_integer(tile, "agent_count"),
+            deciders: try_to_read_deciders(tile, "deciders"),
+            utilities: try_to_read_utilities(tile, "utilities", resources),
+            membership_rule: try_to_read_string(tile, "membership_rule"),
+            membership_candidate_count: try_to_read_integer(tile, "membership_candidate_count"),
+        })
+        .collect()
+}
+
+fn try_to_read_action_params(entry: &Value, searched_var: &str) -> BTreeMap<String, ActionParams> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    read_action_params(value).unwrap_or_else(|e| panic!("{} are invalid: {}", searched_var, e))
+}
+
+fn try_to_read_utility(entry: &Value, searched_var: &str, resources: &[String]) -> UtilityFunction {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    read_utility(value, resources)
+}
+
+fn try_to_read_utilities(entry: &Value, searched_var: &str, resources: &[String]) -> Vec<(UtilityFunction, usize)> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_array().unwrap()
+        .iter()
+        .map(|utility| (read_utility(utility, resources), try_to_read_integer(utility, "count")))
+        .collect()
+}
+
+fn read_utility(value: &Value, resources: &[String]) -> UtilityFunction {
+    let kind = match try_to_read_string(value, "kind").as_str() {
+        "log" => AnyUtility::Log,
+        "linear" => AnyUtility::Linear,
+        "crra" => AnyUtility::Crra(try_to_read_float(value, "risk_aversion")),
+        other => panic!("Unknown utility: {}", other),
+    };
+    let weights = match value.get("weights") {
+        Some(weights) => weights.as_table().unwrap()
+            .iter()
+            .map(|(resource, _)| {
+                (read_resource(resource, resources), try_to_read_float(weights, resource))
+            })
+            .collect(),
+        None => BTreeMap::new(),
+    };
+    UtilityFunction::new(kind, weights)
+}
+
+fn try_to_read_strings(entry: &Value, searched_var: &str) -> Vec<String> {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_array().unwrap()
+        .iter()
+        .map(|string| string.as_str().unwrap().to_string())
+        .collect()
+}
+
+fn try_to_read_bool(entry: &Value, searched_var: &str) -> bool {
+    let value = entry.get(searched_var).unwrap_or_else(|| panic!("{} variable not found", searched_var));
+    value.as_bool().unwrap()
+}
+
+/// Reads configs from the given file, or from config.toml found in the working directory.
+pub fn read_configs(config_pathname: Option<&str>) -> Configs {
+    let toml_files: Vec<PathBuf> = match config_pathname {
+        Some(pathname) => vec![PathBuf::from(pathname)],
+        None => WalkDir::new(".")
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| 
+                entry.file_type().is_file() &&
+                entry.file_name().to_string_lossy().ends_with(".toml"))
+            .map(|entry| entry.into_path() )
+            .collect(),
+    };
     
     for file in &toml_files {
-        if file.file_name().unwrap() == "config.toml" {
+        if config_pathname.is_some() || file.file_name().unwrap() == "config.toml" {
             println!("File {:?} found", file);
             let toml_map: Value = fs::read_to_string(file).unwrap().parse().unwrap();
 
             if let Some(Value::Array(config_map)) = toml_map.get("Configs") {
-                for entry in config_map {
+                if let Some(entry) = config_map.first() {
 
                     let c1 = "plot_graph";
                     let c2 = "plotting_frame_subselection_factor";
                     let c3 = "tick_count";
-                    let c4 = "agent_count";
-                    let c5 = "seed";
+                    let c4 = "seed";
+                    let c5 = "both_collaborate_payoff";
+                    let c6 = "cheater_payoff";
+                    let c7 = "cheated_payoff";
+                    let c8 = "both_cheat_payoff";
+                    let c9 = "collaboration_reputation_change";
+                    let c10 = "cheating_reputation_change";
+                    let c11 = "reservoir_resources";
+                    let c12 = "reservoir_deciders";
+                    let c13 = "tiles";
+                    let c14 = "resources";
+                    let c15 = "reservoir_utility";
+                    let c16 = "lookahead_rollouts";
+                    let c17 = "lookahead_depth";
+                    let c18 = "exploration_rate";
+                    let c19 = "learning_rate";
+                    let c20 = "write_time_series";
+                    let c21 = "time_series_subselection_factor";
+                    let c22 = "write_event_log";
+                    let c23 = "actions";
 
-                    let plot_graph = {
-                        let value = entry.get(c1).expect(&format!("{} config not found", c1));
-                        let extracted_value = value.as_bool().unwrap();
-                        extracted_value
-                    };
-                    
+                    let plot_graph = try_to_read_bool(entry, c1);
                     let plotting_frame_subselection_factor = try_to_read_integer(entry, c2);
                     let tick_count = try_to_read_integer(entry, c3);
-                    let agent_count = try_to_read_integer(entry, c4);
-                    let seed = try_to_read_integer(entry, c5); 
+                    let seed = try_to_read_integer(entry, c4); 
+                    let both_collaborate_payoff = try_to_read_signed_integer(entry, c5);
+                    let cheater_payoff = try_to_read_signed_integer(entry, c6);
+                    let cheated_payoff = try_to_read_signed_integer(entry, c7);
+                    let both_cheat_payoff = try_to_read_signed_integer(entry, c8);
+                    let collaboration_reputation_change = try_to_read_float(entry, c9);
+                    let cheating_reputation_change = try_to_read_float(entry, c10);
+                    let resources = try_to_read_strings(entry, c14);
+                    let reservoir_resources = try_to_read_resource_ranges(entry, c11, &resources);
+                    let reservoir_deciders = try_to_read_weighted_deciders(entry, c12);
+                    let tiles = try_to_read_tiles(entry, c13, &resources);
+                    let reservoir_utility = try_to_read_utility(entry, c15, &resources);
+                    let decider_params = DeciderParams {
+                        lookahead_rollouts: try_to_read_integer(entry, c16),
+                        lookahead_depth: try_to_read_integer(entry, c17),
+                        exploration_rate: try_to_read_float(entry, c18),
+                        learning_rate: try_to_read_float(entry, c19),
+                    };
+                    assert!(decider_params.lookahead_rollouts > 0 && decider_params.lookahead_depth > 0, "{} and {} should be positive", c16, c17);
+                    assert!((0.0..=1.0).contains(&decider_params.exploration_rate) && (0.0..=1.0).contains(&decider_params.learning_rate), "{} and {} should be within [0, 1]", c18, c19);
+                    let write_time_series = try_to_read_bool(entry, c20);
+                    let time_series_subselection_factor = try_to_read_integer(entry, c21);
+                    let write_event_log = try_to_read_bool(entry, c22);
+                    let action_params = try_to_read_action_params(entry, c23);
 
                     let configs = Configs { 
                         plot_graph,
                         plotting_frame_subselection_factor,
+                        write_time_series,
+                        time_series_subselection_factor,
+                        write_event_log,
+                        action_params,
                         tick_count,
-                        agent_count,
                         seed,
+                        both_collaborate_payoff,
+                        cheater_payoff,
+                        cheated_payoff,
+                        both_cheat_payoff,
+                        collaboration_reputation_change,
+                        cheating_reputation_change,
+                        resources,
+                        reservoir_resources,
+                        reservoir_deciders,
+                        reservoir_utility,
+                        decider_params,
+                        tiles,
                     };
                     
                     println!("{:#?}\n", configs);
@@ -76,7 +279,76 @@ pub fn read_configs() -> Configs {
     panic!("config.toml was not read") 
 }
 
-pub fn plot_resource_distribution(tile: &Tile, root: &mut DrawingArea<BitMapBackend<'_>, Shift>, tick_number: usize) {
+/// Writes metrics of the final state as a single-row CSV file, so that metrics of many runs can be compared.
+/// Gini coefficient of a resource is computed over agents of the tile.
+pub fn write_final_metrics(directory: &str, tiles: &[Tile], games_played: usize) {
+    let mut metrics: Vec<(String, String)> = vec![("games_played".to_string(), games_played.to_string())];
+    for (tile_id, tile) in tiles.iter().enumerate() {
+        let average_utility = tile.agents.values().map(|agent| agent.get_utility()).sum::<f64>() / usize::max(tile.agents.len(), 1) as f64;
+        metrics.push((format!("tile{}_agent_count", tile_id), tile.agents.len().to_string()));
+        metrics.push((format!("tile{}_average_utility", tile_id), format!("{:.4}", average_utility)));
+        for resource in AnyResource::iter() {
+            let amounts = tile.agents.values().map(|agent| *agent.resources.get(&resource).unwrap_or(&0)).collect::<Vec<usize>>();
+            metrics.push((format!("tile{}_{}_agents", tile_id, resource), amounts.iter().sum::<usize>().to_string()));
+            metrics.push((format!("tile{}_{}_treasury", tile_id, resource), tile.resources.get(&resource).unwrap_or(&0).to_string()));
+            metrics.push((format!("tile{}_{}_gini", tile_id, resource), format!("{:.4}", get_gini(&amounts))));
+        }
+    }
+
+    let (names, values): (Vec<String>, Vec<String>) = metrics.into_iter().unzip();
+    fs::write(format!("{}/final_metrics.csv", directory), format!("{}\n{}\n", names.join(","), values.join(","))).unwrap();
+}
+
+fn get_gini(amounts: &[usize]) -> f64 {
+    let total = amounts.iter().sum::<usize>() as f64;
+    if total == 0.0 {
+        return 0.0;
+    }
+    let mut sorted = amounts.to_vec();
+    sorted.sort();
+    let weighted_sum = sorted.iter().enumerate().map(|(i, amount)| (i + 1) as f64 * *amount as f64).sum::<f64>();
+    let count = sorted.len() as f64;
+    (2.0 * weighted_sum) / (count * total) - (count + 1.0) / count
+}
+
+/// Per-tick time series in CSV files, one row per agent and one row per tile for every sampled tick.
+/// Only agents that are members of tiles are written, reservoir agents are not.
+pub struct TimeSeriesWriter {
+    agents: BufWriter<File>, // tick,tile,agent_id,<resources>
+    tiles: BufWriter<File>, // tick,tile,agent_count,games_played,<treasury resources>
+}
+
+impl TimeSeriesWriter {
+    pub fn new(directory: &str) -> TimeSeriesWriter {
+        let resources = AnyResource::iter().map(|resource| resource.to_string()).collect::<Vec<String>>().join(",");
+        let mut agents = BufWriter::new(File::create(format!("{}/agent_resources.csv", directory)).unwrap());
+        let mut tiles = BufWriter::new(File::create(format!("{}/tile_resources.csv", directory)).unwrap());
+        writeln!(agents, "tick,tile,agent_id,{}", resources).unwrap();
+        writeln!(tiles, "tick,tile,agent_count,games_played,{}", resources).unwrap();
+        TimeSeriesWriter {agents, tiles}
+    }
+
+    /// Writes state of all tiles after the tick. Games played are counted per host tile.
+    pub fn record(&mut self, tick: usize, tiles: &[Tile], games_played: &[usize]) {
+        let amounts = |resources: &BTreeMap<AnyResource, usize>| AnyResource::iter()
+            .map(|resource| resources.get(&resource).unwrap_or(&0).to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        for (tile_id, tile) in tiles.iter().enumerate() {
+            for (agent_id, agent) in &tile.agents {
+                writeln!(self.agents, "{},{},{},{}", tick, tile_id, agent_id, amounts(&agent.resources)).unwrap();
+            }
+            writeln!(self.tiles, "{},{},{},{},{}", tick, tile_id, tile.agents.len(), games_played[tile_id], amounts(&tile.resources)).unwrap();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.agents.flush().unwrap();
+        self.tiles.flush().unwrap();
+    }
+}
+
+pub fn plot_resource_distribution(tiles: &[Tile], resource: &AnyResource, root: &mut DrawingArea<BitMapBackend<'_>, Shift>, tick_number: usize) {
     let max_log_resource_for_plotting = 4.0;
     let plot_height = 10u32;
     let bucket_count = 100;
@@ -87,33 +359,35 @@ pub fn plot_resource_distribution(tile: &Tile, root: &mut DrawingArea<BitMapBack
     let tick_info = &format!("Tick: {}", tick_number);
     
     root.fill(&WHITE).unwrap();
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .margin(5)
-        .caption("Coin distribution", ("sans-serif", 30))
+        .caption(format!("{} distribution", resource), ("sans-serif", 30))
         .x_label_area_size(40)
         .y_label_area_size(40)
         .build_cartesian_2d(0.0..max_log_resource_for_plotting, 0..plot_height)
         .unwrap();
-    chart.configure_mesh().x_desc("log10(Coin)").y_desc("N").draw().unwrap();
+    chart.configure_mesh().x_desc(format!("log10({})", resource)).y_desc("N").draw().unwrap();
 
     let mut buckets = vec![0u32; bucket_count];
     let mut rectangles_to_draw = vec![];
     let mut ids_to_draw = vec![];
 
-    let agent_resources = tile.agents.iter()
-        .map(|agent| *agent.resources.get(&AnyResource::Coins).unwrap() as f64)
+    let agent_resources = tiles.iter()
+        .flat_map(|tile| tile.agents.values())
+        .map(|agent| *agent.resources.get(resource).unwrap() as f64)
         .collect::<Vec<f64>>();
+    let agent_ids = tiles.iter().flat_map(|tile| tile.agents.keys()).collect::<Vec<&AgentID>>();
 
-    let agents_plus_tile_resources = {   // Tile resource value is the last element in vec
+    let agents_plus_tile_resources = {   // Tile resource values are the last elements in vec, in order of tiles
         let mut resources = agent_resources.clone();
-        resources.push(*tile.resources.get(&AnyResource::Coins).unwrap() as f64);  
+        resources.extend(tiles.iter().map(|tile| *tile.resources.get(resource).unwrap() as f64));
         resources
     };
 
-    for (agent_id, resource) in agents_plus_tile_resources.iter().enumerate() {
+    for (position, resource) in agents_plus_tile_resources.iter().enumerate() {
         let log_resource = f64::log10(*resource);
         let bucket_index = min(((log_resource / max_log_resource_for_plotting) * (bucket_count as f64 - 1.0)).floor() as usize, bucket_count-1); 
-        let relative_position = agent_id as f32 / agent_resources.len() as f32;
+        let relative_position = position as f32 / agent_resources.len() as f32;
         let color = colormap.get_color(relative_position);
 
         let bar_left = bucket_index as f64 * bucket_width;
@@ -128,10 +402,10 @@ pub fn plot_resource_distribution(tile: &Tile, root: &mut DrawingArea<BitMapBack
                     color.filled())
             );
     
-            let padded_id = if !(agent_id == agents_plus_tile_resources.len() - 1) {
-                format!("{:<3}", agent_id) // For Agents
+            let padded_id = if position < agent_ids.len() {
+                format!("{:<3}", agent_ids[position]) // For Agents
             } else {
-                "T  ".to_string() // For Tile
+                format!("T{:<2}", position - agent_ids.len()) // For Tiles
             };
                 
             ids_to_draw.push(
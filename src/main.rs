@@ -2,58 +2,225 @@ use std::fs;
 use std::time::Instant;
 use std::vec;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
 use std::fs::write;
 use rand::{rngs::StdRng, SeedableRng};
-use strum::IntoEnumIterator;
 use plotters::prelude::*;
 use rand::prelude::SliceRandom;
 
 mod io;
 mod implementation;
+mod templates;
+mod reservoir;
+mod membership;
+mod exchange;
+mod schedule;
+mod providers;
+mod market;
+mod memory;
+mod events;
+mod sweep;
 
 use io::*;
-use implementation::{AnyResource, AnyRole};
-use implementation::{get_initializer, get_pool_provider, get_agent_assigner};
+use implementation::{AnyResource, AnyRole, define_resources, learn, get_known_actions, get_param_range};
+use implementation::{get_initializer, get_pool_provider, get_agent_assigner, get_reservoir, get_membership_rule, get_game_validator, get_provider_initializer};
+use reservoir::{Reservoir, ParticipationStats};
+use membership::{MembershipRecord, update_membership, membership_summary};
+use exchange::{PlayerExchange, PoolDescription};
+use schedule::Schedule;
+use market::{Market, clear_market};
+use memory::{AnyMemoryValue, CURRENT_GAME, Memory, remember_game};
+use events::EventLog;
+use sweep::run_sweep;
 
 type AgentID = usize;
+type TileID = usize; // Index of the tile in the simulation
 type Resources = BTreeMap<AnyResource, usize>;
 type DecisionAvailableData = BTreeMap<AgentID, Resources>;
-type ActionFn = fn(&mut Tile, AgentID, &mut StdRng);
-type TransformerFn = fn(Vec<ActionFn>) -> Vec<ActionFn>;
-type DeciderFn = fn(&Tile, AgentID, Vec<ActionFn>, &DecisionAvailableData, &mut StdRng) -> ActionFn;
-type AgentInitializerFn = fn(&Configs) -> Vec<Agent>;
-type PoolProviderFn = fn(&mut Vec<Game>, usize);
-type AgentAssignerFn = fn(game: &Game, available_agents: &mut Vec<Agent>) -> Option<BTreeMap<AgentID, AnyRole>>;
-type ParticipationCheckerFn = fn(&Agent, &Game, &AnyRole) -> bool;
-
-type ReputationMatrix = Vec<Vec<f64>>;
+type ActionFn = fn(&mut Tile, AgentID, &Action, &mut StdRng); // Takes the action itself to read its parameters
+type ActionParams = BTreeMap<String, f64>;
+type TransformerFn = fn(Vec<Action>) -> Vec<Action>;
+type DeciderFn = fn(&Tile, AgentID, Vec<Choice>, &DecisionAvailableData, &mut Memory, &BTreeMap<AgentID, AnyRole>, &mut StdRng) -> Choice; // Reputations are on the tile
+type AgentInitializerFn = fn(&TileConfigs, DeciderParams, AgentID) -> Vec<Agent>; // Takes ID of the first agent of the tile
+type ReservoirInitializerFn = fn(&Configs) -> Reservoir;
+type ScheduledProviders<'a> = Vec<(Box<dyn GameProvider + 'a>, Schedule)>; // Every game provider with its own schedule
+type PoolProviderFn = fn(&mut Vec<Game>, &mut ScheduledProviders, usize, &mut StdRng);
+type ProviderInitializerFn = for<'a> fn(&'a Configs, &mut StdRng) -> ScheduledProviders<'a>; // Providers are built once before the simulation starts
+type GameValidatorFn = fn(&Configs) -> Result<(), GameValidationError>;
+type AgentAssignerFn = fn(game: &Game, game_index: usize, exchange: &mut PlayerExchange, reputations: &ReputationMatrix, rng: &mut StdRng) -> Option<BTreeMap<AgentID, AnyRole>>;
+type ParticipationCheckerFn = fn(&Agent, &Game, &AnyRole, &ReputationMatrix) -> bool;
+type MembershipRuleFn = fn(agent: &Agent, tile: &Tile, baseline: &ParticipationStats) -> bool; // Returns true if agent should be a member of the tile
+
+type ResourceChanges = BTreeMap<AnyResource, i64>;
+type OutcomeMatrix = BTreeMap<BTreeMap<AnyRole, String>, Outcome>; // Maps option chosen by every role to the outcome of this combination
 
 #[derive(Clone, Debug)]
 pub struct Agent {
     resources: Resources,
-    base_actions: Vec<ActionFn>,
+    base_actions: Vec<Action>,
     participation_checker: ParticipationCheckerFn,
     decider: DeciderFn,
+    utility: UtilityFunction,
+    decider_params: DeciderParams,
+    memory: Memory,
     id: AgentID,
 }
 
+/// Utility of an agent is the weighted sum of utilities of amounts of its resources (see docs/Basic.md).
+#[derive(Clone, Debug, Default)]
+pub struct UtilityFunction {
+    kind: AnyUtility,
+    weights: BTreeMap<AnyResource, f64>, // Resources without weight have weight 1
+}
+
+/// Parameters of deciders, the same for all agents (see config.toml).
+#[derive(Clone, Copy, Debug)]
+pub struct DeciderParams {
+    pub lookahead_rollouts: usize, // Monte Carlo rollouts per action of utility decider
+    pub lookahead_depth: usize, // Actions per rollout of utility decider. The first one is the evaluated action, the rest are chosen at random
+    pub exploration_rate: f64, // Probability of a random choice of bandit decider
+    pub learning_rate: f64, // Step size of value updates of bandit decider, constant so that values follow changes of the environment
+}
+
 #[derive(Clone)]
 pub struct Game {
+    name: String, // Name of the game in logs
     roles: BTreeMap<AnyRole, RoleDescription>,
     consequent_game: Option<Box<Game>>,
+    outcomes: Option<OutcomeMatrix>, // Games with outcome matrix are resolved after all options are chosen
+    resolution: AnyResolution,
+    tile: TileID, // Game is played on this tile, with its treasury and reputations
+    assignment: AnyAssignment,
+    action_params: BTreeMap<String, ActionParams>, // Parameters of actions by action name, override their defaults
 }
 
 #[derive(Clone)]
 pub struct RoleDescription {
     uniqueness: AnyUniqueness,
     transformer: TransformerFn,
+    options: Vec<String>, // If not empty, role chooses one of these options instead of an action
+    pool: PoolDescription, // Pool of the players exchange that role players are drawn from
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Outcome {
+    resource_changes: BTreeMap<AnyRole, ResourceChanges>,
+    reputation_changes: BTreeMap<AnyRole, BTreeMap<AnyRole, f64>>, // Change of the view that observer role has of target role
+}
+
+#[derive(Clone, Debug)]
+pub struct ReputationMatrix {
+    initial_reputation: f64,
+    reputations: BTreeMap<(AgentID, AgentID), f64>, // Reputation of target in the eyes of observer. Only changed values are stored
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TileChanges {
+    agents: BTreeMap<AgentID, ResourceChanges>,
+    tile: ResourceChanges,
+    reputations: BTreeMap<(AgentID, AgentID), f64>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Choice {
+    Action(Action), // Executed immediately
+    Option(String), // Resolved through outcome matrix of the game
+}
+
+/// Action with a stable name, a description and parameters. Clones share the same description, so they are cheap.
+#[derive(Clone)]
+pub struct Action(Arc<ActionDescription>);
+
+struct ActionDescription {
+    name: String,
+    description: String,
+    params: ActionParams,
+    function: ActionFn,
+}
+
+impl Choice {
+    fn name(&self) -> String {
+        match self {
+            Choice::Action(action) => action.name().to_string(),
+            Choice::Option(option) => option.clone(),
+        }
+    }
+}
+
+impl Action {
+    fn new(name: &str, description: &str, params: &[(&str, f64)], function: ActionFn) -> Action {
+        let params = params.iter().map(|(param, value)| (param.to_string(), *value)).collect();
+        Action(Arc::new(ActionDescription {name: name.to_string(), description: description.to_string(), params, function}))
+    }
+
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    fn param(&self, param: &str) -> f64 {
+        *self.0.params.get(param).unwrap_or_else(|| panic!("Action {} has no parameter {}", self.name(), param))
+    }
+
+    /// Returns the same action with some of its parameters changed.
+    fn with_params(&self, params: &ActionParams) -> Action {
+        let mut description = ActionDescription {
+            name: self.0.name.clone(),
+            description: self.0.description.clone(),
+            params: self.0.params.clone(),
+            function: self.0.function,
+        };
+        description.params.extend(params.iter().map(|(param, value)| (param.clone(), *value)));
+        Action(Arc::new(description))
+    }
+
+    fn execute(&self, tile: &mut Tile, agent_id: AgentID, rng: &mut StdRng) {
+        (self.0.function)(tile, agent_id, self, rng)
+    }
+}
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.params.is_empty() {
+            write!(f, "{}", self.name())
+        } else {
+            write!(f, "{}{:?}", self.name(), self.0.params)
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Tile {
-    agents: Vec<Agent>,
+    agents: BTreeMap<AgentID, Agent>,
     resources: Resources,
-    _reputations: ReputationMatrix,
+    reputations: ReputationMatrix,
+    history: BTreeMap<(AgentID, AgentID), Vec<Interaction>>, // Interactions of agent with opponent, in order of play
+    membership_rule: MembershipRuleFn,
+    memberships: BTreeMap<AgentID, MembershipRecord>, // Records of all agents that were members of the tile
+    market: Market,
+}
+
+#[derive(Clone, Debug)]
+pub enum GameValidationError {
+    MissingRoleDescription(AnyRole),
+    MinGreaterThanMax(AnyRole, usize, usize), // Contains min and max multiplicity of the role
+    ZeroMaxMultiplicity(AnyRole),
+    UnknownGame(String), // Provider spec names a game that is neither hardcoded nor a template
+    UnknownRole(String), // Provider spec sets pool of a role the game does not have
+    UnknownTile(TileID),
+    UnknownAction(String), // Action parameters are set for an action that does not exist
+    UnknownActionParam(String, String), // Contains action name and parameter name
+    InvalidActionParam(String, String, f64), // Contains action name, parameter name and its value out of the valid range
+}
+
+#[derive(Clone, Debug)]
+pub struct Interaction {
+    own_option: String,
+    opponent_option: String,
 }
 
 #[derive(PartialEq, Clone)]
@@ -62,138 +229,732 @@ pub enum AnyUniqueness { // TODO: make other name, see  Modality
     OptionalMultipletRole(usize, usize), // Contains min required and max possible multiplicity. Can be assigned
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AnyAgentSource {
+    Tile, // Role is played by agents of the tile where the game is played
+    OtherTile(TileID), // Role is played by agents of another tile, visiting the tile of the game
+    Reservoir, // Role is played by agents drawn from the reservoir for this game only
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AnyAssignment { // Order in which agents of a pool are offered roles of the game
+    UniformRandom,
+    WeightedByResources, // Agents with more resources are more likely to be offered roles first
+    WeightedByReputation, // Agents with better reputation among other agents of the pool are more likely to be offered roles first
+    RoundRobin, // Agents are offered roles in order of IDs, continuing after the agent assigned last time
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AnyResolution {
+    Sequential, // Agents decide and act one after another on the live tile
+    Simultaneous, // Agents decide against the same snapshot, joint outcome is applied in one step
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum AnyUtility { // Utility of a single resource amount
+    #[default]
+    Log, // log10(amount) + 1 for positive amounts, 0 otherwise
+    Linear, // Amount itself
+    Crra(f64), // Constant relative risk aversion with the given coefficient, of amount + 1, so that zero amount has zero utility
+}
+
 impl Agent {
-    fn new(initial_resources: Resources, base_actions: Vec<ActionFn>, decider: DeciderFn, participation_checker: ParticipationCheckerFn, id: AgentID) -> Agent {
+    fn new(initial_resources: Resources, base_actions: Vec<Action>, decider: DeciderFn, decider_params: DeciderParams, utility: UtilityFunction, participation_checker: ParticipationCheckerFn, id: AgentID) -> Agent {
         let mut zeroed_resources = AnyResource::iter().map(|r| (r, 0)).collect::<Resources>();
         for (resource, amount) in initial_resources {
             zeroed_resources.insert(resource, amount);
         }
-        Agent {resources: zeroed_resources, base_actions, decider, participation_checker, id}
+        Agent {resources: zeroed_resources, base_actions, decider, utility, decider_params, memory: Memory::default(), participation_checker, id}
     }
 
     fn get_utility(&self) -> f64 {
-        let mut total_utility = 0.0;
-        for (_resource, &amount) in &self.resources {
-            if amount > 0 {
-                total_utility += f64::log10(amount as f64) + 1.0;
-                    // We add constant to the resource amount because without it utility of agent with 1 resource will be 0.
-                    // This is so because log10(1) == 0.
-            }
-        }
-        total_utility
+        self.utility.get_utility(&self.resources)
+    }
+
+}
+
+impl UtilityFunction {
+    fn new(kind: AnyUtility, weights: BTreeMap<AnyResource, f64>) -> UtilityFunction {
+        UtilityFunction {kind, weights}
+    }
+
+    fn get_utility(&self, resources: &Resources) -> f64 {
+        resources.iter()
+            .map(|(resource, &amount)| self.weights.get(resource).unwrap_or(&1.0) * self.get_amount_utility(amount))
+            .sum()
     }
 
+    fn get_amount_utility(&self, amount: usize) -> f64 {
+        let amount = amount as f64;
+        match self.kind {
+            AnyUtility::Log => if amount > 0.0 {f64::log10(amount) + 1.0} else {0.0},
+                // We add constant to the resource amount because without it utility of agent with 1 resource will be 0.
+                // This is so because log10(1) == 0.
+            AnyUtility::Linear => amount,
+            AnyUtility::Crra(1.0) => f64::ln(amount + 1.0),
+            AnyUtility::Crra(risk_aversion) => (f64::powf(amount + 1.0, 1.0 - risk_aversion) - 1.0) / (1.0 - risk_aversion),
+        }
+    }
 }
 
 
 impl Tile {
-    fn new(agents: Vec<Agent>, resources: Resources, reputations: Vec<Vec<f64>>) -> Tile {
+    fn new(agents: Vec<Agent>, resources: Resources, reputations: ReputationMatrix, membership_rule: MembershipRuleFn) -> Tile {
         let mut zeroed_resources = AnyResource::iter().map(|r| (r, 0)).collect::<Resources>();
         for (resource, amount) in resources {
             zeroed_resources.insert(resource, amount);
         }
 
-        Tile{agents, resources: zeroed_resources, _reputations: reputations}
+        let memberships = agents.iter().map(|agent| (agent.id, MembershipRecord::default())).collect();
+        let agents = agents.into_iter().map(|agent| (agent.id, agent)).collect();
+        Tile{agents, resources: zeroed_resources, reputations, history: BTreeMap::new(), membership_rule, memberships, market: Market::default()}
+    }
+
+    pub fn get_agent_mut(&mut self, agent_id: AgentID) -> &mut Agent {
+        self.agents.get_mut(&agent_id).expect("No agent with this ID on the tile")
+    }
+
+    fn record_interactions(&mut self, assigned_roles: &BTreeMap<AgentID, AnyRole>, chosen_options: &BTreeMap<AnyRole, String>) {
+        for (agent_id, role) in assigned_roles {
+            for (opponent_id, opponent_role) in assigned_roles {
+                if agent_id != opponent_id {
+                    let interaction = Interaction {
+                        own_option: chosen_options[role].clone(),
+                        opponent_option: chosen_options[opponent_role].clone(),
+                    };
+                    self.history.entry((*agent_id, *opponent_id)).or_default().push(interaction);
+                }
+            }
+        }
+    }
+
+    /// Removes agents together with their reputations and history. Used when reservoir agents leave the tile after a game.
+    fn forget_agents(&mut self, agent_ids: &[AgentID]) {
+        self.agents.retain(|agent_id, _| !agent_ids.contains(agent_id));
+        self.reputations.reputations.retain(|(observer, target), _| !agent_ids.contains(observer) && !agent_ids.contains(target));
+        self.history.retain(|(agent_id, opponent_id), _| !agent_ids.contains(agent_id) && !agent_ids.contains(opponent_id));
+    }
+
+    pub fn get_history(&self, agent_id: AgentID, opponent_id: AgentID) -> &[Interaction] {
+        self.history.get(&(agent_id, opponent_id)).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+impl ReputationMatrix {
+    fn new(initial_reputation: f64) -> ReputationMatrix {
+        ReputationMatrix {initial_reputation, reputations: BTreeMap::new()}
+    }
+
+    pub fn get(&self, observer: AgentID, target: AgentID) -> f64 {
+        *self.reputations.get(&(observer, target)).unwrap_or(&self.initial_reputation)
+    }
+
+    pub fn change(&mut self, observer: AgentID, target: AgentID, change: f64) {
+        let reputation = self.get(observer, target) + change;
+        self.reputations.insert((observer, target), f64::max(reputation, 0.0)); // Reputation can not go below zero
+    }
+
+    /// Returns reputation of every agent of the group averaged over views of all other agents of the group.
+    pub fn get_group_averages(&self, group: &[AgentID]) -> BTreeMap<AgentID, f64> {
+        let mut changes = group.iter().map(|agent_id| (*agent_id, 0.0)).collect::<BTreeMap<AgentID, f64>>();
+        for (&(observer, target), reputation) in &self.reputations {
+            if observer != target && changes.contains_key(&observer) {
+                if let Some(change) = changes.get_mut(&target) {
+                    *change += reputation - self.initial_reputation; // Only changed values are stored, others are initial
+                }
+            }
+        }
+        let observer_count = usize::max(group.len().saturating_sub(1), 1) as f64;
+        changes.into_iter()
+            .map(|(agent_id, change)| (agent_id, self.initial_reputation + change / observer_count))
+            .collect()
+    }
+}
+
+impl fmt::Display for GameValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameValidationError::MissingRoleDescription(role) => write!(f, "No description for this role: {:?}", role),
+            GameValidationError::MinGreaterThanMax(role, min, max) => write!(f, "Role {:?} has min multiplicity {} greater than max multiplicity {}", role, min, max),
+            GameValidationError::ZeroMaxMultiplicity(role) => write!(f, "Role {:?} has zero max multiplicity", role),
+            GameValidationError::UnknownGame(game) => write!(f, "Unknown game: {}", game),
+            GameValidationError::UnknownRole(role) => write!(f, "Game has no role {}", role),
+            GameValidationError::UnknownTile(tile) => write!(f, "No tile with index {}", tile),
+            GameValidationError::UnknownAction(action) => write!(f, "Unknown action: {}", action),
+            GameValidationError::UnknownActionParam(action, param) => write!(f, "Action {} has no parameter {}", action, param),
+            GameValidationError::InvalidActionParam(action, param, value) => write!(f, "Parameter {} of action {} has invalid value {}", param, action, value),
+        }
     }
 }
 
 pub trait GameProvider {
     fn provide_game(&self) -> Game;
-    fn check_if_all_roles_are_described(&self, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), String>;
+    /// Checks that the provided game describes all roles the provider expects. Roles of every game in the chain
+    /// are checked by Game::validate.
+    fn check_if_all_roles_are_described(&self, _roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<(), GameValidationError> {
+        Ok(())
+    }
+
+    /// Validates provided game together with all its consequent games. Called once before the simulation starts.
+    fn validate(&self) -> Result<(), GameValidationError> {
+        let game = self.provide_game();
+        self.check_if_all_roles_are_described(&game.roles)?;
+        game.validate()
+    }
 }
 
 impl Game {
-    fn prepare_actions(&self, assigned_roles: &BTreeMap<AgentID, AnyRole>, ordered_agents: &Vec<Agent>) -> BTreeMap<AgentID, Vec<ActionFn>> {
-        let mut transient_actions: BTreeMap<AgentID, Vec<ActionFn>> = BTreeMap::new();
+    fn prepare_choices(&self, assigned_roles: &BTreeMap<AgentID, AnyRole>, agents: &BTreeMap<AgentID, Agent>) -> BTreeMap<AgentID, Vec<Choice>> {
+        let mut transient_choices: BTreeMap<AgentID, Vec<Choice>> = BTreeMap::new();
 
         for (id, role) in assigned_roles.iter() {
-            let actions = ordered_agents[*id].base_actions.clone();
-            let transformer_fn = self.roles.get(role).unwrap().transformer;
-            let transformed_actions = transformer_fn(actions);
-            transient_actions.insert(*id, transformed_actions);
+            let description = self.roles.get(role).unwrap();
+            let choices = if description.options.is_empty() {
+                let actions = agents[id].base_actions.clone();
+                let transformed_actions = (description.transformer)(actions);
+                transformed_actions.into_iter()
+                    .map(|action| match self.action_params.get(action.name()) {
+                        Some(params) => action.with_params(params),
+                        None => action,
+                    })
+                    .map(Choice::Action)
+                    .collect()
+            } else {
+                description.options.iter().cloned().map(Choice::Option).collect()
+            };
+            transient_choices.insert(*id, choices);
         }
-        transient_actions
+        transient_choices
     }
 
-    fn prepare_and_execute_actions(&self, tile: &mut Tile, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> () {
-        let immutable_ordered_agents = &tile.agents.clone();
-        let immutable_tile = &tile.clone();
-        let transient_actions = self.prepare_actions(&assigned_roles, immutable_ordered_agents);
-        for (agent_id, actions) in transient_actions {
-            let decider_fn = &immutable_ordered_agents[agent_id].decider;
-
-            let availiable_data: BTreeMap<AgentID, Resources> = immutable_ordered_agents
-                .iter()
-                .map(|agent| (agent.id, agent.resources.clone()))
-                .collect();
-
-            let choosen_action = decider_fn(immutable_tile, agent_id, actions, &availiable_data, rng);
-            choosen_action(tile, agent_id, rng) // Tile is mutated here
+    /// Returns choices made by all assigned agents.
+    fn prepare_and_execute_actions(&self, tile: &mut Tile, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> BTreeMap<AgentID, Choice> {
+        let transient_choices = self.prepare_choices(assigned_roles, &tile.agents);
+        let chosen_choices = match self.resolution {
+            AnyResolution::Sequential => execute_sequentially(tile, &self.name, assigned_roles, transient_choices, rng),
+            AnyResolution::Simultaneous => execute_simultaneously(tile, &self.name, assigned_roles, transient_choices, rng),
+        };
+
+        if let Some(outcomes) = &self.outcomes {
+            let chosen_options = chosen_choices.iter()
+                .filter_map(|(agent_id, choice)| match choice {
+                    Choice::Option(option) => Some((assigned_roles[agent_id].clone(), option.clone())),
+                    Choice::Action(_) => None,
+                })
+                .collect::<BTreeMap<AnyRole, String>>();
+            let outcome = outcomes.get(&chosen_options).expect("Outcome matrix does not cover chosen options");
+            if outcome.is_affordable(tile, assigned_roles) {
+                outcome.apply(tile, assigned_roles);
+            } // Otherwise the combination is declined, as somebody can not pay for it
+            tile.record_interactions(assigned_roles, &chosen_options);
         }
+        chosen_choices
     }
+
     pub fn create_delayed_consequent_game(delay: usize, game: Game) -> Game {
         if delay == 0 {
-            return game.clone();
+            game
         } else {
             let roles: BTreeMap<AnyRole, RoleDescription> = BTreeMap::new();
             let delayed_game = Game::create_delayed_consequent_game(delay - 1, game);
-            let game = Game {roles, consequent_game: Some(Box::new(delayed_game))};
-            game
+            Game {name: "delay".to_string(), roles, consequent_game: Some(Box::new(delayed_game)), outcomes: None, resolution: AnyResolution::Sequential, tile: 0, assignment: AnyAssignment::UniformRandom, action_params: BTreeMap::new()}
         }
     }
 
+    /// Checks multiplicities and descriptions of all roles and that action parameters refer to existing actions
+    /// and are within valid ranges, for the game and the whole chain of its consequent games.
+    fn validate(&self) -> Result<(), GameValidationError> {
+        let known_actions = get_known_actions();
+        for (name, params) in &self.action_params {
+            let action = known_actions.iter().find(|action| action.name() == name)
+                .ok_or_else(|| GameValidationError::UnknownAction(name.clone()))?;
+            if let Some(param) = params.keys().find(|param| !action.0.params.contains_key(*param)) {
+                return Err(GameValidationError::UnknownActionParam(name.clone(), param.clone()));
+            }
+            if let Some((param, value)) = params.iter().find(|(param, value)| !get_param_range(name, param).contains(*value)) {
+                return Err(GameValidationError::InvalidActionParam(name.clone(), param.clone(), *value));
+            }
+        }
+
+        for (role, description) in &self.roles {
+            let (min, max) = match description.uniqueness {
+                AnyUniqueness::RequiredMultipletRole(min, max) |
+                AnyUniqueness::OptionalMultipletRole(min, max) => (min, max),
+            };
+            if max == 0 {
+                return Err(GameValidationError::ZeroMaxMultiplicity(role.clone()));
+            }
+            if min > max {
+                return Err(GameValidationError::MinGreaterThanMax(role.clone(), min, max));
+            }
+        }
+
+        self.check_if_all_roles_are_described()?;
+
+        match &self.consequent_game {
+            Some(game) => game.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Every role of the outcome matrix and every role sharing the enum with a described role should be described.
+    fn check_if_all_roles_are_described(&self) -> Result<(), GameValidationError> {
+        let outcome_roles = self.outcomes.iter().flat_map(|outcomes| outcomes.keys()).flat_map(|combination| combination.keys());
+        let sibling_roles = self.roles.keys().flat_map(AnyRole::siblings).collect::<Vec<AnyRole>>();
+        for role in outcome_roles.chain(&sibling_roles) {
+            if !self.roles.contains_key(role) {
+                return Err(GameValidationError::MissingRoleDescription(role.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets parameters of actions for the game together with all its consequent games.
+    /// Parameters that are already set are overridden, other parameters are kept.
+    pub fn with_action_params(mut self, action_params: &BTreeMap<String, ActionParams>) -> Game {
+        for (name, params) in action_params {
+            self.action_params.entry(name.clone()).or_default().extend(params.iter().map(|(param, value)| (param.clone(), *value)));
+        }
+        self.consequent_game = self.consequent_game.map(|game| Box::new(game.with_action_params(action_params)));
+        self
+    }
+
+    /// Moves the game together with all its consequent games to the given tile.
+    pub fn on_tile(mut self, tile: TileID) -> Game {
+        self.tile = tile;
+        self.consequent_game = self.consequent_game.map(|game| Box::new(game.on_tile(tile)));
+        self
+    }
+
+}
+
+impl Outcome {
+    /// Returns true if every agent has enough resources to pay its part of the outcome.
+    fn is_affordable(&self, tile: &Tile, assigned_roles: &BTreeMap<AgentID, AnyRole>) -> bool {
+        assigned_roles.iter().all(|(agent_id, role)| {
+            let resources = &tile.agents[agent_id].resources;
+            self.resource_changes.get(role).into_iter().flatten()
+                .all(|(resource, change)| *resources.get(resource).unwrap_or(&0) as i64 + change >= 0)
+        })
+    }
+
+    fn apply(&self, tile: &mut Tile, assigned_roles: &BTreeMap<AgentID, AnyRole>) {
+        for (agent_id, role) in assigned_roles {
+            if let Some(changes) = self.resource_changes.get(role) {
+                apply_resource_changes(&mut tile.get_agent_mut(*agent_id).resources, changes);
+            }
+            if let Some(changes) = self.reputation_changes.get(role) {
+                for (target_id, target_role) in assigned_roles {
+                    if let Some(change) = changes.get(target_role) {
+                        tile.reputations.change(*agent_id, *target_id, *change);
+                    }
+                }
+            }
+        }
+    }
 }
 
+impl TileChanges {
+    /// Resource changes of agents and treasury of the tile, without reputation changes.
+    fn of_resources(agents_before: &BTreeMap<AgentID, Agent>, treasury_before: &Resources, after: &Tile) -> TileChanges {
+        let agents = after.agents.iter()
+            .filter_map(|(agent_id, agent_after)| Some((*agent_id, get_resource_changes(&agents_before.get(agent_id)?.resources, &agent_after.resources))))
+            .filter(|(_, changes)| !changes.is_empty())
+            .collect();
+        let tile = get_resource_changes(treasury_before, &after.resources);
+        TileChanges {agents, tile, reputations: BTreeMap::new()}
+    }
+
+    fn between(before: &Tile, after: &Tile) -> TileChanges {
+        let agents = after.agents.iter()
+            .map(|(agent_id, agent_after)| (*agent_id, get_resource_changes(&before.agents[agent_id].resources, &agent_after.resources)))
+            .filter(|(_, changes)| !changes.is_empty())
+            .collect();
+        let tile = get_resource_changes(&before.resources, &after.resources);
+        let reputations = before.reputations.reputations.keys()
+            .chain(after.reputations.reputations.keys())
+            .map(|&(observer, target)| ((observer, target), after.reputations.get(observer, target) - before.reputations.get(observer, target)))
+            .filter(|(_, change)| *change != 0.0)
+            .collect();
+        TileChanges {agents, tile, reputations}
+    }
 
+    fn add(&mut self, other: TileChanges) {
+        for (agent_id, changes) in other.agents {
+            add_resource_changes(self.agents.entry(agent_id).or_default(), &changes);
+        }
+        add_resource_changes(&mut self.tile, &other.tile);
+        for (pair, change) in other.reputations {
+            *self.reputations.entry(pair).or_insert(0.0) += change;
+        }
+    }
+
+    fn apply(&self, tile: &mut Tile) {
+        for (agent_id, changes) in &self.agents {
+            apply_resource_changes(&mut tile.get_agent_mut(*agent_id).resources, changes);
+        }
+        apply_resource_changes(&mut tile.resources, &self.tile);
+        for (&(observer, target), change) in &self.reputations {
+            tile.reputations.change(observer, target, *change);
+        }
+    }
+}
+
+fn get_total_resources<'a>(agents: impl Iterator<Item = &'a Agent>) -> Resources {
+    let mut total_resources = AnyResource::iter().map(|r| (r, 0)).collect::<Resources>();
+    for agent in agents {
+        for (resource, amount) in &agent.resources {
+            *total_resources.entry(resource.clone()).or_insert(0) += amount;
+        }
+    }
+    total_resources
+}
+
+fn get_resource_changes(before: &Resources, after: &Resources) -> ResourceChanges {
+    after.iter()
+        .map(|(resource, &amount)| (resource.clone(), amount as i64 - *before.get(resource).unwrap_or(&0) as i64))
+        .filter(|(_, change)| *change != 0)
+        .collect()
+}
+
+fn add_resource_changes(changes: &mut ResourceChanges, other: &ResourceChanges) {
+    for (resource, change) in other {
+        *changes.entry(resource.clone()).or_insert(0) += change;
+    }
+}
+
+fn apply_resource_changes(resources: &mut Resources, changes: &ResourceChanges) {
+    for (resource, change) in changes {
+        let amount = resources.entry(resource.clone()).or_insert(0);
+        *amount = amount.saturating_add_signed(*change as isize); // Resources can not go below zero
+    }
+}
+
+/// Returns the choice together with memory of the agent as changed by its decider.
+fn decide(tile: &Tile, agent_id: AgentID, choices: Vec<Choice>, game_name: &str, assigned_roles: &BTreeMap<AgentID, AnyRole>, rng: &mut StdRng) -> (Choice, Memory) {
+    let decider_fn = tile.agents[&agent_id].decider;
+    let mut memory = tile.agents[&agent_id].memory.clone();
+    memory.insert(CURRENT_GAME, AnyMemoryValue::Text(game_name.to_string()));
+
+    let availiable_data: DecisionAvailableData = tile.agents
+        .iter()
+        .map(|(agent_id, agent)| (*agent_id, agent.resources.clone()))
+        .collect();
+
+    let choice = decider_fn(tile, agent_id, choices, &availiable_data, &mut memory, assigned_roles, rng);
+    (choice, memory)
+}
+
+/// Every agent decides and acts in turn, so later agents see the tile already changed by earlier ones.
+/// Returns choices of all agents. Chosen options are resolved through outcome matrix of the game.
+fn execute_sequentially(tile: &mut Tile, game_name: &str, assigned_roles: &BTreeMap<AgentID, AnyRole>, transient_choices: BTreeMap<AgentID, Vec<Choice>>, rng: &mut StdRng) -> BTreeMap<AgentID, Choice> {
+    let mut chosen_choices: BTreeMap<AgentID, Choice> = BTreeMap::new();
+    for (agent_id, choices) in transient_choices {
+        let (choice, memory) = decide(tile, agent_id, choices, game_name, assigned_roles, rng);
+        tile.get_agent_mut(agent_id).memory = memory;
+        if let Choice::Action(choosen_action) = &choice {
+            choosen_action.execute(tile, agent_id, rng); // Tile is mutated here
+        }
+        chosen_choices.insert(agent_id, choice);
+    }
+    chosen_choices
+}
+
+/// Every agent first commits to a choice against the same snapshot of the tile.
+/// Chosen actions are then evaluated against this snapshot and their joint changes are applied in one step.
+fn execute_simultaneously(tile: &mut Tile, game_name: &str, assigned_roles: &BTreeMap<AgentID, AnyRole>, transient_choices: BTreeMap<AgentID, Vec<Choice>>, rng: &mut StdRng) -> BTreeMap<AgentID, Choice> {
+    let snapshot = tile.clone();
+    let mut committed_choices: BTreeMap<AgentID, Choice> = BTreeMap::new();
+    for (agent_id, choices) in transient_choices {
+        let (choice, memory) = decide(&snapshot, agent_id, choices, game_name, assigned_roles, rng);
+        tile.get_agent_mut(agent_id).memory = memory;
+        committed_choices.insert(agent_id, choice);
+    }
+
+    let mut joint_changes = TileChanges::default();
+    for (agent_id, choice) in &committed_choices {
+        if let Choice::Action(choosen_action) = choice {
+            let mut tile_clone = snapshot.clone();
+            choosen_action.execute(&mut tile_clone, *agent_id, rng);
+            joint_changes.add(TileChanges::between(&snapshot, &tile_clone));
+        }
+    }
+    joint_changes.apply(tile); // Tile is mutated here
+    committed_choices
+}
+
+
+/// Returns the value that follows the named command line argument.
+fn get_argument(arguments: &[String], name: &str) -> Option<String> {
+    let position = arguments.iter().position(|argument| argument == name)?;
+    Some(arguments.get(position + 1).unwrap_or_else(|| panic!("No value for argument {}", name)).clone())
+}
+
+/// Runs a single simulation. Arguments: --config <file> (config.toml of the working directory by default),
+/// --output <directory> (output by default). With --sweep <spec> runs a parameter sweep instead (see src/sweep.rs).
 fn main() {
     let timer: Instant = Instant::now();
+    let arguments = std::env::args().collect::<Vec<String>>();
+    if let Some(spec_pathname) = get_argument(&arguments, "--sweep") {
+        run_sweep(&spec_pathname);
+        return;
+    }
 
-    fs::create_dir_all("output").unwrap();
-    let log_file_pathname = format!("output/{}.txt", "final_state");
-    let plot_file_pathname = format!("output/{}.gif", "resources_distribution");
-    let mut root = BitMapBackend::gif(&plot_file_pathname, (640, 480), 100).unwrap().into_drawing_area();
-
-    let configs = read_configs();
+    let output_directory = get_argument(&arguments, "--output").unwrap_or("output".to_string());
+    fs::create_dir_all(&output_directory).unwrap();
+    let log_file_pathname = format!("{}/{}.txt", output_directory, "final_state");
+
+    let configs = read_configs(get_argument(&arguments, "--config").as_deref());
+    define_resources(&configs.resources); // Resources should be defined before anything refers to them
+    let plot_file_pathnames = AnyResource::iter()
+        .map(|resource| (resource.clone(), format!("{}/{}_distribution.gif", output_directory, resource)))
+        .collect::<Vec<(AnyResource, String)>>();
+    let mut roots = plot_file_pathnames.iter()
+        .map(|(resource, pathname)| (resource, BitMapBackend::gif(pathname, (640, 480), 100).unwrap().into_drawing_area()))
+        .collect::<Vec<_>>();
     let pool_provider = get_pool_provider();
     let agent_assigner = get_agent_assigner();
-    let reputations = vec![vec![1f64; configs.agent_count]; configs.agent_count];
+    get_game_validator()(&configs).unwrap_or_else(|e| panic!("Invalid game: {}", e));
     let mut rng = StdRng::seed_from_u64(configs.seed as u64);
-    let mut tile = Tile::new(get_initializer()(&configs), BTreeMap::new(), reputations);
+    let mut tiles: Vec<Tile> = vec![];
+    let mut first_agent_id = 0;
+    for tile_configs in &configs.tiles {
+        let agents = get_initializer()(tile_configs, configs.decider_params, first_agent_id);
+        first_agent_id += agents.len();
+        tiles.push(Tile::new(agents, BTreeMap::new(), ReputationMatrix::new(1.0), get_membership_rule(&tile_configs.membership_rule)));
+    }
+    let mut reservoir = get_reservoir()(&configs);
     let mut games: Vec<Game> = vec![];
+    let mut exchange = PlayerExchange::default();
+    let mut providers = get_provider_initializer()(&configs, &mut rng);
+    let mut time_series = configs.write_time_series.then(|| TimeSeriesWriter::new(&output_directory));
+    let mut event_log = configs.write_event_log.then(|| EventLog::new(&format!("{}/events.jsonl", output_directory)));
+    let mut next_game_id = 0;
+    let mut total_games_played = 0;
 
     for tick in 0..configs.tick_count {
         let mut consequent_games: Vec<Game> = vec![];
-        pool_provider(&mut games, tick);
+        let mut games_played = vec![0; tiles.len()];
+        pool_provider(&mut games, &mut providers, tick, &mut rng);
         games.shuffle(&mut rng);
 
-        let mut transient_consumable_agents = tile.agents.clone();
-        // transient_consumable_agents.shuffle(& mut rng);
+        exchange.populate(&games, &tiles, &mut reservoir, &mut rng);
 
-        for suggested_game in &games{
-            let maybe_assigned_agents = agent_assigner(&suggested_game, &mut transient_consumable_agents);
+        for (game_index, suggested_game) in games.iter().enumerate() {
+            let host = suggested_game.tile;
+            let game_id = next_game_id;
+            next_game_id += 1;
+            let maybe_assigned_agents = agent_assigner(suggested_game, game_index, &mut exchange, &tiles[host].reputations, &mut rng);
             if let Some(assigned_agents) = maybe_assigned_agents {
-                suggested_game.prepare_and_execute_actions(&mut tile, &assigned_agents, &mut rng);
+                let reservoir_agent_ids = assigned_agents.keys()
+                    .filter(|agent_id| exchange.get_reservoir_agent(**agent_id).is_some())
+                    .copied()
+                    .collect::<Vec<AgentID>>();
+                let visitors = assigned_agents.keys()
+                    .filter_map(|agent_id| {
+                        let home = tiles.iter().position(|tile| tile.agents.contains_key(agent_id))?;
+                        (home != host).then_some((*agent_id, home))
+                    })
+                    .collect::<BTreeMap<AgentID, TileID>>();
+                for agent_id in &reservoir_agent_ids { // Reservoir agents join the tile for this game only
+                    tiles[host].agents.insert(*agent_id, exchange.get_reservoir_agent(*agent_id).unwrap().clone());
+                }
+                for (agent_id, home) in &visitors { // Agents of other tiles visit the host tile for this game only
+                    let agent = tiles[*home].agents.remove(agent_id).unwrap();
+                    tiles[host].agents.insert(*agent_id, agent);
+                }
+
+                let agents_before = tiles[host].agents.clone();
+                let treasury_before = tiles[host].resources.clone();
+                let chosen_choices = suggested_game.prepare_and_execute_actions(&mut tiles[host], &assigned_agents, &mut rng);
+                if let Some(log) = event_log.as_mut().filter(|_| !assigned_agents.is_empty()) {
+                    let changes = TileChanges::of_resources(&agents_before, &treasury_before, &tiles[host]);
+                    log.record_game(tick, game_id, suggested_game, &assigned_agents, &chosen_choices, &changes);
+                }
+                for (agent_id, choice) in &chosen_choices { // Learning agents update the value of their choice by the utility change
+                    let utility_change = tiles[host].agents[agent_id].get_utility() - agents_before[agent_id].get_utility();
+                    let opponents = assigned_agents.keys().filter(|id| *id != agent_id).copied().collect();
+                    let agent = tiles[host].get_agent_mut(*agent_id);
+                    remember_game(agent, opponents, choice, utility_change);
+                    learn(agent, &suggested_game.name, &choice.name(), utility_change);
+                }
+                for agent_id in assigned_agents.keys() {
+                    let agent_after = tiles[host].agents[agent_id].clone();
+                    let home = *visitors.get(agent_id).unwrap_or(&host);
+                    let stats = if reservoir_agent_ids.contains(agent_id) {
+                        reservoir.get_baseline_mut(host) // Reservoir agents are compared with members of the tile they played on
+                    } else {
+                        &mut tiles[home].memberships.get_mut(agent_id).unwrap().stats
+                    };
+                    stats.record(&agents_before[agent_id], &agent_after);
+                }
+
+                for (agent_id, home) in &visitors {
+                    let agent = tiles[host].agents.remove(agent_id).unwrap();
+                    tiles[*home].agents.insert(*agent_id, agent);
+                }
+                tiles[host].forget_agents(&reservoir_agent_ids);
+                if !assigned_agents.is_empty() {
+                    games_played[host] += 1; // Games without roles only delay their consequent games
+                }
                 if let Some(gamebox) = &suggested_game.consequent_game {
                     consequent_games.push(*gamebox.clone()); // If played game had a consequent game, push a consequent game to the pool (will be used for the next tick).
                 }
+            } else if let Some(log) = event_log.as_mut() { // Some required role could not be filled
+                log.record_unplayed_game(tick, game_id, suggested_game);
             }
         }
         games.clear();
         games.append(&mut consequent_games);
+        for (tile_id, (tile, tile_configs)) in tiles.iter_mut().zip(&configs.tiles).enumerate() {
+            clear_market(tile);
+            update_membership(tile, tile_id, &mut reservoir, tile_configs.membership_candidate_count, &mut rng);
+        }
 
+        total_games_played += games_played.iter().sum::<usize>();
+        if let Some(writer) = time_series.as_mut().filter(|_| tick.is_multiple_of(configs.time_series_subselection_factor)) {
+            writer.record(tick, &tiles, &games_played);
+        }
         if configs.plot_graph && (tick % configs.plotting_frame_subselection_factor) == 0 {
             println!("Plotting frame for tick {}", tick);
-            plot_resource_distribution(&tile, &mut root, tick);
+            for (resource, root) in roots.iter_mut() {
+                plot_resource_distribution(&tiles, resource, root, tick);
+            }
         }
     }
 
     let mut summary_log = String::new();
     summary_log.push_str(&format!("{:#?}\n\n", configs));
-    summary_log.push_str(&format!("Tile Resources{:#?}\n\n", tile.resources));
-    summary_log.push_str(&format!("Tile Agents {:#?}\n\n", tile.agents));
+    summary_log.push_str("Actions\n");
+    for action in get_known_actions() {
+        summary_log.push_str(&format!("{:?}: {}\n", action, action.description()));
+    }
+    summary_log.push('\n');
+    for (tile_id, tile) in tiles.iter().enumerate() {
+        summary_log.push_str(&format!("Tile {} Resources{:#?}\n\n", tile_id, tile.resources));
+        summary_log.push_str(&format!("Tile {} Total Agent Resources{:#?}\n\n", tile_id, get_total_resources(tile.agents.values())));
+        summary_log.push_str(&format!("Tile {} Agents {:#?}\n\n", tile_id, tile.agents));
+        summary_log.push_str(&format!("Tile {} Reputations {:#?}\n\n", tile_id, tile.reputations));
+        summary_log.push_str(&format!("Tile {} Market {:#?}\n\n", tile_id, tile.market));
+        summary_log.push_str(&format!("Tile {} Memories\n", tile_id));
+        for agent in tile.agents.values().filter(|agent| !agent.memory.is_empty()) {
+            summary_log.push_str(&format!("Agent {}: {}\n", agent.id, agent.memory));
+        }
+        summary_log.push('\n');
+        summary_log.push_str(&format!("Tile {} Memberships\n", tile_id));
+        summary_log.push_str(&membership_summary(tile, &reservoir.get_baseline(tile_id)));
+    }
     write(&log_file_pathname, summary_log).unwrap();
+    write_final_metrics(&output_directory, &tiles, total_games_played);
 
     println!("\nSee final state: {}", log_file_pathname);
-    println!("See plot: {}", plot_file_pathname);
+    if let Some(writer) = time_series.as_mut() {
+        writer.flush();
+        println!("See time series: {0}/agent_resources.csv, {0}/tile_resources.csv", output_directory);
+    }
+    if let Some(log) = event_log.as_mut() {
+        log.flush();
+        println!("See event log: {}/events.jsonl", output_directory);
+    }
+    for (_, pathname) in &plot_file_pathnames {
+        println!("See plot: {}", pathname);
+    }
     println!("Execution time: {:.3} s", timer.elapsed().as_secs_f64());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use implementation::define_test_resources;
+
+    fn food() -> AnyResource {
+        AnyResource::Defined("Food".to_string())
+    }
+
+    fn first_choice_decider(_tile: &Tile, _agent_id: AgentID, choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, _roles: &BTreeMap<AgentID, AnyRole>, _rng: &mut StdRng) -> Choice {
+        choices[0].clone()
+    }
+
+    fn any_participation_checker(_agent: &Agent, _game: &Game, _role: &AnyRole, _reputations: &ReputationMatrix) -> bool {
+        true
+    }
+
+    fn closed_membership_rule(agent: &Agent, tile: &Tile, _baseline: &ParticipationStats) -> bool {
+        tile.agents.contains_key(&agent.id)
+    }
+
+    /// Takes Coins from the treasury as seen by the agent, all other agents think worse of the agent.
+    fn take_from_treasury_action(tile: &mut Tile, agent_id: AgentID, action: &Action, _rng: &mut StdRng) {
+        let amount = usize::min(action.param("amount") as usize, tile.resources[&AnyResource::Coins]);
+        *tile.resources.get_mut(&AnyResource::Coins).unwrap() -= amount;
+        *tile.get_agent_mut(agent_id).resources.get_mut(&AnyResource::Coins).unwrap() += amount;
+        let observers = tile.agents.keys().filter(|id| **id != agent_id).copied().collect::<Vec<AgentID>>();
+        for observer in observers {
+            tile.reputations.change(observer, agent_id, -0.25);
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} should be {}", actual, expected);
+    }
+
+    #[test]
+    fn amount_utilities_have_known_values() {
+        let log = UtilityFunction::new(AnyUtility::Log, BTreeMap::new());
+        assert_close(log.get_amount_utility(0), 0.0);
+        assert_close(log.get_amount_utility(1), 1.0);
+        assert_close(log.get_amount_utility(100), 3.0);
+
+        let linear = UtilityFunction::new(AnyUtility::Linear, BTreeMap::new());
+        assert_close(linear.get_amount_utility(0), 0.0);
+        assert_close(linear.get_amount_utility(7), 7.0);
+
+        let crra_log = UtilityFunction::new(AnyUtility::Crra(1.0), BTreeMap::new()); // Limit of the general formula is ln(amount + 1)
+        assert_close(crra_log.get_amount_utility(0), 0.0);
+        assert_close(crra_log.get_amount_utility(9), f64::ln(10.0));
+
+        let crra_half = UtilityFunction::new(AnyUtility::Crra(0.5), BTreeMap::new());
+        assert_close(crra_half.get_amount_utility(0), 0.0);
+        assert_close(crra_half.get_amount_utility(3), 2.0); // (4^0.5 - 1) / 0.5
+
+        let crra_two = UtilityFunction::new(AnyUtility::Crra(2.0), BTreeMap::new());
+        assert_close(crra_two.get_amount_utility(9), 0.9); // (10^-1 - 1) / -1
+    }
+
+    #[test]
+    fn resources_without_weight_have_weight_one() {
+        let resources = BTreeMap::from([(AnyResource::Coins, 3), (food(), 4)]);
+        let utility = UtilityFunction::new(AnyUtility::Linear, BTreeMap::from([(AnyResource::Coins, 2.0)]));
+        assert_close(utility.get_utility(&resources), 10.0);
+
+        let utility = UtilityFunction::new(AnyUtility::Log, BTreeMap::from([(food(), 0.5)]));
+        assert_close(utility.get_utility(&BTreeMap::from([(AnyResource::Coins, 10), (food(), 100)])), 2.0 + 0.5 * 3.0);
+    }
+
+    #[test]
+    fn simultaneous_actions_see_the_same_snapshot() {
+        define_test_resources();
+        let utility = UtilityFunction::new(AnyUtility::Log, BTreeMap::new());
+        let decider_params = DeciderParams {lookahead_rollouts: 1, lookahead_depth: 1, exploration_rate: 0.0, learning_rate: 0.0};
+        let agents = (0..3)
+            .map(|id| Agent::new(BTreeMap::new(), vec![], first_choice_decider, decider_params, utility.clone(), any_participation_checker, id))
+            .collect();
+        let treasury = BTreeMap::from([(AnyResource::Coins, 100)]);
+        let mut tile = Tile::new(agents, treasury, ReputationMatrix::new(1.0), closed_membership_rule);
+
+        let action = Action::new("take_from_treasury", "", &[("amount", 60.0)], take_from_treasury_action);
+        let role = AnyRole::TemplateRole("player".to_string());
+        let assigned_roles = BTreeMap::from([(0, role.clone()), (1, role)]);
+        let transient_choices = assigned_roles.keys().map(|agent_id| (*agent_id, vec![Choice::Action(action.clone())])).collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        execute_simultaneously(&mut tile, "test", &assigned_roles, transient_choices, &mut rng);
+
+        assert_eq!(tile.agents[&0].resources[&AnyResource::Coins], 60); // Both saw 100 Coins in the treasury
+        assert_eq!(tile.agents[&1].resources[&AnyResource::Coins], 60);
+        assert_eq!(tile.resources[&AnyResource::Coins], 0); // Joint change of -120 is saturated
+        for (observer, target) in [(1, 0), (2, 0), (0, 1), (2, 1)] {
+            assert_eq!(tile.reputations.get(observer, target), 0.75); // Every change is applied once
+        }
+        assert_eq!(tile.reputations.get(0, 0), 1.0);
+    }
+}
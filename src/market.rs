@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, BTreeSet};
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use super::*;
+
+type ResourcePair = (AnyResource, AnyResource); // Base resource is traded for quote resource
+
+/// Exchange market of the tile (see global providers in docs/runtime.md). Agents post orders during market games,
+/// orders are matched once per tick and are not carried to the next tick.
+#[derive(Clone, Debug, Default)]
+pub struct Market {
+    orders: Vec<Order>,
+    prices: BTreeMap<ResourcePair, f64>, // Price of the last trade of every pair
+    volumes: BTreeMap<ResourcePair, usize>, // Total amount of base resource traded
+}
+
+#[derive(Clone, Debug)]
+pub struct Order {
+    agent_id: AgentID,
+    side: AnySide,
+    pair: ResourcePair,
+    quantity: usize, // Amount of base resource to buy or sell
+    limit_price: f64, // Highest price for bids, lowest price for asks
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AnySide {
+    Bid, // Buys base resource for quote resource
+    Ask, // Sells base resource for quote resource
+}
+
+impl Market {
+    /// Returns price of the last trade of the pair, or None before the first trade.
+    pub fn get_price(&self, pair: &ResourcePair) -> Option<f64> {
+        self.prices.get(pair).copied()
+    }
+
+    /// Posts an order of random resource for Coins, with limit price uniform within last price * (1 ± price_noise)
+    /// and quantity of at most max_quantity, parameters are read from the action. Before the first trade of the pair
+    /// initial_price is used as the last price. Agent does not post anything if it can not afford any quantity.
+    pub fn post_random_order(&mut self, agent: &Agent, side: AnySide, action: &Action, rng: &mut StdRng) {
+        let bases = AnyResource::iter().filter(|resource| *resource != AnyResource::Coins).collect::<Vec<AnyResource>>();
+        let Some(base) = bases.choose(rng) else {
+            return; // Nothing to trade for Coins
+        };
+        let pair = (base.clone(), AnyResource::Coins);
+        let price_noise = action.param("price_noise");
+        let factor = if price_noise > 0.0 {rng.gen_range(1.0 - price_noise..1.0 + price_noise)} else {1.0};
+        let limit_price = self.get_price(&pair).unwrap_or(action.param("initial_price")) * factor;
+
+        let affordable_quantity = match side {
+            AnySide::Bid => (agent.resources[&pair.1] as f64 / limit_price).floor() as usize,
+            AnySide::Ask => agent.resources[&pair.0],
+        };
+        let max_quantity = usize::min(affordable_quantity, action.param("max_quantity") as usize);
+        if max_quantity > 0 {
+            let quantity = rng.gen_range(1..=max_quantity);
+            self.orders.push(Order {agent_id: agent.id, side, pair, quantity, limit_price});
+        }
+    }
+}
+
+/// Matches orders of every pair: highest bids are matched with lowest asks of other agents while bid price is not lower than ask price.
+/// Every trade happens at the middle of bid and ask prices, quantity is limited by current resources of both agents.
+/// Orders of agents that are not on the tile anymore are dropped.
+pub fn clear_market(tile: &mut Tile) {
+    let orders = std::mem::take(&mut tile.market.orders);
+    let pairs = orders.iter().map(|order| order.pair.clone()).collect::<BTreeSet<ResourcePair>>();
+
+    for pair in pairs {
+        let select = |side| orders.iter()
+            .filter(|order| order.pair == pair && order.side == side && tile.agents.contains_key(&order.agent_id))
+            .cloned()
+            .collect::<Vec<Order>>();
+        let mut bids = select(AnySide::Bid);
+        let mut asks = select(AnySide::Ask);
+        bids.sort_by(|a, b| b.limit_price.total_cmp(&a.limit_price)); // Stable sort keeps earlier orders first
+        asks.sort_by(|a, b| a.limit_price.total_cmp(&b.limit_price));
+
+        for bid in bids.iter_mut() {
+            for ask in asks.iter_mut() {
+                if bid.quantity == 0 || bid.limit_price < ask.limit_price {
+                    break; // Bid is filled or the remaining asks are too expensive for it
+                }
+                if ask.quantity == 0 || bid.agent_id == ask.agent_id {
+                    continue; // Agents do not trade with themselves, their asks are left for other bids
+                }
+
+                let price = (bid.limit_price + ask.limit_price) / 2.0;
+                let buyer_quote = tile.agents[&bid.agent_id].resources[&pair.1];
+                let seller_base = tile.agents[&ask.agent_id].resources[&pair.0];
+                if seller_base == 0 {
+                    ask.quantity = 0; // Seller has nothing left to sell
+                    continue;
+                }
+                let quantity = [bid.quantity, ask.quantity, seller_base, (buyer_quote as f64 / price).floor() as usize]
+                    .into_iter()
+                    .min()
+                    .unwrap();
+                if quantity == 0 {
+                    break; // Buyer can not afford anything at this or higher prices
+                }
+
+                let cost = usize::min((price * quantity as f64).round() as usize, buyer_quote);
+                let (buyer_id, seller_id) = (bid.agent_id, ask.agent_id);
+                let buyer = &mut tile.get_agent_mut(buyer_id).resources;
+                *buyer.get_mut(&pair.1).unwrap() -= cost;
+                *buyer.get_mut(&pair.0).unwrap() += quantity;
+                let seller = &mut tile.get_agent_mut(seller_id).resources;
+                *seller.get_mut(&pair.0).unwrap() -= quantity;
+                *seller.get_mut(&pair.1).unwrap() += cost;
+
+                tile.market.prices.insert(pair.clone(), price);
+                *tile.market.volumes.entry(pair.clone()).or_insert(0) += quantity;
+
+                bid.quantity -= quantity;
+                ask.quantity -= quantity;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementation::define_test_resources;
+
+    fn first_choice_decider(_tile: &Tile, _agent_id: AgentID, choices: Vec<Choice>, _data: &DecisionAvailableData, _memory: &mut Memory, _roles: &BTreeMap<AgentID, AnyRole>, _rng: &mut StdRng) -> Choice {
+        choices[0].clone()
+    }
+
+    fn any_participation_checker(_agent: &Agent, _game: &Game, _role: &AnyRole, _reputations: &ReputationMatrix) -> bool {
+        true
+    }
+
+    fn closed_membership_rule(agent: &Agent, tile: &Tile, _baseline: &ParticipationStats) -> bool {
+        tile.agents.contains_key(&agent.id)
+    }
+
+    fn food() -> AnyResource {
+        AnyResource::Defined("Food".to_string())
+    }
+
+    /// Tile with agents holding given amounts of Coins and Food.
+    fn get_tile(holdings: &[(usize, usize)]) -> Tile {
+        define_test_resources();
+        let agents = holdings.iter().enumerate()
+            .map(|(id, (coins, foods))| {
+                let resources = BTreeMap::from([(AnyResource::Coins, *coins), (food(), *foods)]);
+                let utility = UtilityFunction::new(AnyUtility::Log, BTreeMap::new());
+                Agent::new(resources, vec![], first_choice_decider, DeciderParams {lookahead_rollouts: 1, lookahead_depth: 1, exploration_rate: 0.0, learning_rate: 0.0}, utility, any_participation_checker, id)
+            })
+            .collect();
+        Tile::new(agents, BTreeMap::new(), ReputationMatrix::new(1.0), closed_membership_rule)
+    }
+
+    fn post(tile: &mut Tile, agent_id: AgentID, side: AnySide, quantity: usize, limit_price: f64) {
+        tile.market.orders.push(Order {agent_id, side, pair: (food(), AnyResource::Coins), quantity, limit_price});
+    }
+
+    fn holdings(tile: &Tile, agent_id: AgentID) -> (usize, usize) {
+        let resources = &tile.agents[&agent_id].resources;
+        (resources[&AnyResource::Coins], resources[&food()])
+    }
+
+    #[test]
+    fn bid_is_partially_filled_by_cheapest_asks() {
+        let mut tile = get_tile(&[(100, 0), (0, 10), (0, 10)]);
+        post(&mut tile, 0, AnySide::Bid, 5, 1.2);
+        post(&mut tile, 1, AnySide::Ask, 3, 1.0);
+        post(&mut tile, 2, AnySide::Ask, 4, 1.1);
+        clear_market(&mut tile);
+
+        assert_eq!(holdings(&tile, 1), (3, 7)); // 3 Food at 1.1
+        assert_eq!(holdings(&tile, 2), (2, 8)); // 2 Food at 1.15
+        assert_eq!(holdings(&tile, 0), (95, 5));
+        assert_eq!(tile.market.volumes[&(food(), AnyResource::Coins)], 5);
+        assert_eq!(tile.market.get_price(&(food(), AnyResource::Coins)), Some(1.15));
+    }
+
+    #[test]
+    fn orders_do_not_match_below_ask_price() {
+        let mut tile = get_tile(&[(100, 0), (0, 10)]);
+        post(&mut tile, 0, AnySide::Bid, 5, 0.9);
+        post(&mut tile, 1, AnySide::Ask, 5, 1.0);
+        clear_market(&mut tile);
+
+        assert_eq!(holdings(&tile, 0), (100, 0));
+        assert_eq!(holdings(&tile, 1), (0, 10));
+        assert!(tile.market.orders.is_empty());
+    }
+
+    #[test]
+    fn self_matching_ask_is_left_for_other_bids() {
+        let mut tile = get_tile(&[(100, 10), (100, 0)]);
+        post(&mut tile, 0, AnySide::Bid, 2, 1.2);
+        post(&mut tile, 0, AnySide::Ask, 2, 0.8);
+        post(&mut tile, 1, AnySide::Bid, 2, 1.0);
+        clear_market(&mut tile);
+
+        assert_eq!(holdings(&tile, 0), (102, 8)); // 2 Food at 0.9
+        assert_eq!(holdings(&tile, 1), (98, 2));
+    }
+
+    #[test]
+    fn trade_is_limited_by_resources_of_buyer() {
+        let mut tile = get_tile(&[(3, 0), (0, 10)]);
+        post(&mut tile, 0, AnySide::Bid, 5, 1.0);
+        post(&mut tile, 1, AnySide::Ask, 5, 1.0);
+        clear_market(&mut tile);
+
+        assert_eq!(holdings(&tile, 0), (0, 3));
+        assert_eq!(holdings(&tile, 1), (3, 7));
+    }
+}
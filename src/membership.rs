@@ -0,0 +1,75 @@
+use rand::rngs::StdRng;
+
+use super::*;
+
+/// Membership of a single agent in the tile. Record is kept after the agent leaves, so returning agents continue it.
+#[derive(Clone, Debug, Default)]
+pub struct MembershipRecord {
+    pub stats: ParticipationStats, // Game participations while being a member
+    ticks_as_member: usize,
+    joins: usize,
+    leaves: usize,
+}
+
+/// Members that do not satisfy membership rule of the tile leave to the reservoir, then candidates drawn
+/// from the reservoir join the tile if they satisfy it. Agents keep their IDs when moving between tile and reservoir.
+/// Both are judged against the baseline of reservoir agents that played on this tile.
+pub fn update_membership(tile: &mut Tile, tile_id: TileID, reservoir: &mut Reservoir, candidate_count: usize, rng: &mut StdRng) {
+    let baseline = reservoir.get_baseline(tile_id);
+    let leaving_agent_ids = tile.agents.values()
+        .filter(|agent| !(tile.membership_rule)(agent, tile, &baseline))
+        .map(|agent| agent.id)
+        .collect::<Vec<AgentID>>();
+
+    for agent_id in leaving_agent_ids {
+        let agent = tile.agents.remove(&agent_id).unwrap();
+        tile.memberships.get_mut(&agent_id).unwrap().leaves += 1;
+        reservoir.former_members.insert(agent_id, agent); // Reputations and history are kept in case the agent returns
+    }
+
+    for candidate in reservoir.draw_candidates(candidate_count, rng) {
+        if (tile.membership_rule)(&candidate, tile, &baseline) {
+            reservoir.former_members.remove(&candidate.id);
+            tile.memberships.entry(candidate.id).or_default().joins += 1;
+            tile.agents.insert(candidate.id, candidate);
+        }
+    }
+
+    for agent_id in tile.agents.keys() {
+        tile.memberships.get_mut(agent_id).unwrap().ticks_as_member += 1;
+    }
+}
+
+/// Returns summed participation stats of current members of the tile.
+pub fn get_members_stats(tile: &Tile) -> ParticipationStats {
+    let mut members_stats = ParticipationStats::default();
+    for agent_id in tile.agents.keys() {
+        members_stats.add(&tile.memberships[agent_id].stats);
+    }
+    members_stats
+}
+
+pub fn membership_summary(tile: &Tile, baseline: &ParticipationStats) -> String {
+    let mut former_members_stats = ParticipationStats::default();
+    for (agent_id, record) in &tile.memberships {
+        if !tile.agents.contains_key(agent_id) {
+            former_members_stats.add(&record.stats);
+        }
+    }
+
+    let mut summary = String::new();
+    summary.push_str(&format!("Tile members: {}\n", get_members_stats(tile).summary()));
+    summary.push_str(&format!("Former members (while being members): {}\n", former_members_stats.summary()));
+    summary.push_str(&format!("Reservoir baseline: {}\n\n", baseline.summary()));
+
+    for (agent_id, record) in &tile.memberships {
+        let status = if tile.agents.contains_key(agent_id) {"member"} else {"former member"};
+        summary.push_str(&format!(
+            "Agent {} ({}, {} ticks as member, joined {} times, left {} times). {}, difference from baseline utility change: {:.4}\n",
+            agent_id, status, record.ticks_as_member, record.joins, record.leaves,
+            record.stats.summary(), record.stats.average_utility_change() - baseline.average_utility_change()
+        ));
+    }
+    summary.push('\n');
+    summary
+}
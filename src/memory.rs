@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::*;
+
+pub const ACTION_VALUES: &str = "action_values"; // Learned by learning deciders, agents without it do not learn
+pub const CURRENT_GAME: &str = "current_game"; // Name of the game the agent is deciding in
+pub const LAST_OPPONENTS: &str = "last_opponents";
+pub const LAST_CHOICE: &str = "last_choice";
+pub const LAST_UTILITY_CHANGE: &str = "last_utility_change";
+pub const GAMES_PLAYED: &str = "games_played";
+
+/// Internal variables of the agent (see behaviour in docs/Basic.md). Deciders get memory of their agent
+/// and can change it, actions can change memory of any agent of the tile. Memory changes made by actions
+/// of simultaneous games are not applied, as only resource and reputation changes are joint.
+/// Before every decision the engine records the current game, after every game it records
+/// the other entries above except action values.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    values: BTreeMap<String, AnyMemoryValue>,
+}
+
+#[derive(Clone, Debug)]
+pub enum AnyMemoryValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Agents(Vec<AgentID>),
+    ActionValues(BTreeMap<String, ActionValue>), // Choice names mapped to their learned values
+}
+
+/// Estimate of the utility change that a choice brings to the agent.
+#[derive(Clone, Debug, Default)]
+pub struct ActionValue {
+    pub value: f64,
+    pub updates: usize,
+}
+
+impl Memory {
+    pub fn get(&self, key: &str) -> Option<&AnyMemoryValue> {
+        self.values.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut AnyMemoryValue> {
+        self.values.get_mut(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: AnyMemoryValue) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Display for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.values.iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<String>>();
+        write!(f, "{{{}}}", entries.join(", "))
+    }
+}
+
+impl fmt::Display for AnyMemoryValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnyMemoryValue::Integer(value) => write!(f, "{}", value),
+            AnyMemoryValue::Float(value) => write!(f, "{:.4}", value),
+            AnyMemoryValue::Text(value) => write!(f, "{}", value),
+            AnyMemoryValue::Agents(agent_ids) => write!(f, "{:?}", agent_ids),
+            AnyMemoryValue::ActionValues(action_values) => {
+                let entries = action_values.iter()
+                    .map(|(name, action_value)| format!("{} = {:.4} ({} updates)", name, action_value.value, action_value.updates))
+                    .collect::<Vec<String>>();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+/// Records opponents, choice and utility change of the agent in the game it has just played.
+pub fn remember_game(agent: &mut Agent, opponents: Vec<AgentID>, choice: &Choice, utility_change: f64) {
+    let memory = &mut agent.memory;
+    memory.insert(LAST_OPPONENTS, AnyMemoryValue::Agents(opponents));
+    memory.insert(LAST_CHOICE, AnyMemoryValue::Text(choice.name()));
+    memory.insert(LAST_UTILITY_CHANGE, AnyMemoryValue::Float(utility_change));
+    let games_played = match memory.get(GAMES_PLAYED) {
+        Some(AnyMemoryValue::Integer(games_played)) => *games_played,
+        _ => 0,
+    };
+    memory.insert(GAMES_PLAYED, AnyMemoryValue::Integer(games_played + 1));
+}
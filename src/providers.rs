@@ -0,0 +1,129 @@
+use std::fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::Value;
+use walkdir::WalkDir;
+
+use super::*;
+use super::schedule::AnyFrequency;
+use super::templates::{read_action_params, read_pool, read_string};
+
+/// Game provider spec, read from TOML files in providers/ directory (see docs/game-provider.md).
+/// Every spec creates one provider for every listed tile, each with its own schedule. Example of a spec file:
+///
+/// [[providers]]
+/// game = "basic_two_agent" # Name of a hardcoded game or of a game template
+/// tiles = [0] # Optional, all tiles by default
+/// frequency = { kind = "jittered_period", period = 4.1, jitter = 0.25 }
+/// pools = { Second = { source = "reservoir", pool = "drainable" } } # Optional, overrides pools of the game roles
+/// actions = { pay_tax = { rate = 0.02 } } # Optional, overrides action parameters from configs for games of the provider
+#[derive(Clone, Debug)]
+pub struct ProviderSpec {
+    pub game: String,
+    pub tiles: Option<Vec<TileID>>,
+    pub frequency: AnyFrequency,
+    pub pools: BTreeMap<String, PoolDescription>, // Role names mapped to pools players are drawn from
+    pub action_params: BTreeMap<String, ActionParams>, // Action names mapped to their parameters
+}
+
+pub fn read_provider_specs(directory: &str) -> Vec<ProviderSpec> {
+    if !Path::new(directory).is_dir() {
+        return vec![];
+    }
+
+    let mut spec_files: Vec<PathBuf> = WalkDir::new(directory)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry|
+            entry.file_type().is_file() &&
+            entry.file_name().to_string_lossy().ends_with(".toml"))
+        .map(|entry| entry.into_path())
+        .collect();
+    spec_files.sort(); // Order of providers should not depend on the file system
+
+    spec_files.iter()
+        .flat_map(|file| {
+            println!("Game provider spec {:?} found", file);
+            let specs: Value = fs::read_to_string(file).unwrap().parse().unwrap();
+            let entries = specs.get("providers").and_then(Value::as_array)
+                .unwrap_or_else(|| panic!("Game provider spec {:?} has no providers", file));
+            entries.iter()
+                .map(|entry| read_provider_spec(entry).unwrap_or_else(|e| panic!("Game provider spec {:?} is invalid: {}", file, e)))
+                .collect::<Vec<ProviderSpec>>()
+        })
+        .collect()
+}
+
+fn read_provider_spec(entry: &Value) -> Result<ProviderSpec, String> {
+    let game = read_string(entry, "game")?;
+
+    let tiles = match entry.get("tiles") {
+        None => None,
+        Some(tiles) => Some(tiles.as_array().ok_or("tiles should be an array")?
+            .iter()
+            .map(|tile| tile.as_integer().map(|tile| tile as TileID).ok_or(format!("Tile index {} is not an integer", tile)))
+            .collect::<Result<Vec<TileID>, String>>()?),
+    };
+
+    let frequency = read_frequency(entry.get("frequency").ok_or("frequency table not found")?)?;
+
+    let mut pools = BTreeMap::new();
+    if let Some(pool_entries) = entry.get("pools") {
+        for (role, pool) in pool_entries.as_table().ok_or("pools should be a table of roles")? {
+            pools.insert(role.clone(), read_pool(pool).map_err(|e| format!("Pool of role {} is invalid: {}", role, e))?);
+        }
+    }
+
+    let action_params = match entry.get("actions") {
+        Some(actions) => read_action_params(actions)?,
+        None => BTreeMap::new(),
+    };
+
+    Ok(ProviderSpec {game, tiles, frequency, pools, action_params})
+}
+
+fn read_frequency(entry: &Value) -> Result<AnyFrequency, String> {
+    let read_number = |searched_var: &str| match entry.get(searched_var) {
+        None => Ok(None),
+        Some(value) => value.as_float().or(value.as_integer().map(|value| value as f64)) // Integer values are read as floats
+            .map(Some)
+            .ok_or(format!("{} should be a number", searched_var)),
+    };
+    let read_positive = |searched_var: &str| {
+        let value = read_number(searched_var)?.ok_or(format!("{} variable not found", searched_var))?;
+        if value > 0.0 {Ok(value)} else {Err(format!("{} should be positive", searched_var))}
+    };
+    let read_noise = |searched_var: &str| {
+        let value = read_number(searched_var)?.unwrap_or(0.0);
+        if (0.0..1.0).contains(&value) {Ok(value)} else {Err(format!("{} should be within [0, 1)", searched_var))}
+    };
+
+    match read_string(entry, "kind")?.as_str() {
+        "poisson" => Ok(AnyFrequency::Poisson(read_positive("rate")?)),
+        "jittered_period" => Ok(AnyFrequency::JitteredPeriod(read_positive("period")?, read_noise("jitter")?)),
+        "spread_spectrum" => Ok(AnyFrequency::SpreadSpectrum(read_positive("period")?, read_noise("spread")?)),
+        other => Err(format!("Unknown frequency kind: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(frequency: &str) -> Result<AnyFrequency, String> {
+        read_frequency(&frequency.parse::<Value>().unwrap())
+    }
+
+    #[test]
+    fn integer_values_are_read_as_floats() {
+        assert!(matches!(read("kind = \"poisson\"\nrate = 1"), Ok(AnyFrequency::Poisson(rate)) if rate == 1.0));
+        assert!(matches!(read("kind = \"jittered_period\"\nperiod = 4"), Ok(AnyFrequency::JitteredPeriod(period, jitter)) if period == 4.0 && jitter == 0.0));
+    }
+
+    #[test]
+    fn values_of_wrong_type_are_not_reported_as_missing() {
+        assert_eq!(read("kind = \"poisson\"\nrate = \"1\"").unwrap_err(), "rate should be a number");
+        assert_eq!(read("kind = \"poisson\"").unwrap_err(), "rate variable not found");
+        assert_eq!(read("kind = \"spread_spectrum\"\nperiod = 2\nspread = 1").unwrap_err(), "spread should be within [0, 1)");
+    }
+}
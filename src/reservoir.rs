@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use rand::distributions::{Distribution, Uniform, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::seq::IteratorRandom;
+
+use super::*;
+
+/// Reservoir is an effectively infinite set of agents external to the tile (see docs/Basic.md).
+/// Agents are generated on demand from configured distributions. They play games of a single tick and do not keep
+/// resources between games.
+/// Their outcomes on every tile are tracked as the baseline that members of this tile are compared against.
+/// Reservoir also keeps former members of the tile, they may return to the tile later.
+#[derive(Clone, Debug)]
+pub struct Reservoir {
+    resource_distributions: BTreeMap<AnyResource, Uniform<usize>>,
+    deciders: Vec<(DeciderFn, Memory)>, // Deciders with initial memories of their agents
+    decider_distribution: WeightedIndex<f64>,
+    utility: UtilityFunction,
+    decider_params: DeciderParams,
+    base_actions: Vec<Action>,
+    participation_checker: ParticipationCheckerFn,
+    next_id: AgentID, // IDs are never reused, so every agent keeps its ID while moving between tile and reservoir
+    pub former_members: BTreeMap<AgentID, Agent>,
+    baselines: BTreeMap<TileID, ParticipationStats>, // Outcomes of reservoir agents by the tile they played on
+}
+
+/// Resource and utility changes summed over all game participations of some group of agents.
+#[derive(Clone, Debug, Default)]
+pub struct ParticipationStats {
+    participations: usize,
+    resource_changes: ResourceChanges,
+    utility_change: f64,
+}
+
+impl Reservoir {
+    pub fn new(resource_ranges: &BTreeMap<AnyResource, (usize, usize)>, deciders: Vec<((DeciderFn, Memory), f64)>, decider_params: DeciderParams, utility: UtilityFunction, base_actions: Vec<Action>, participation_checker: ParticipationCheckerFn, first_id: AgentID) -> Reservoir {
+        let resource_distributions = resource_ranges.iter()
+            .map(|(resource, (min, max))| (resource.clone(), Uniform::new_inclusive(*min, *max)))
+            .collect();
+        let decider_distribution = WeightedIndex::new(deciders.iter().map(|(_, weight)| *weight)).unwrap();
+        let deciders = deciders.into_iter().map(|(decider, _)| decider).collect();
+
+        Reservoir {
+            resource_distributions,
+            deciders,
+            decider_distribution,
+            utility,
+            decider_params,
+            base_actions,
+            participation_checker,
+            next_id: first_id,
+            former_members: BTreeMap::new(),
+            baselines: BTreeMap::new(),
+        }
+    }
+
+    fn generate_agent(&mut self, rng: &mut StdRng) -> Agent {
+        let resources = self.resource_distributions.iter()
+            .map(|(resource, distribution)| (resource.clone(), distribution.sample(rng)))
+            .collect();
+        let (decider, memory) = self.deciders[self.decider_distribution.sample(rng)].clone();
+        let mut agent = Agent::new(resources, self.base_actions.clone(), decider, self.decider_params, self.utility.clone(), self.participation_checker, self.next_id);
+        agent.memory = memory;
+        self.next_id += 1;
+        agent
+    }
+
+    /// Returns outcomes of reservoir agents that played on the tile.
+    pub fn get_baseline(&self, tile: TileID) -> ParticipationStats {
+        self.baselines.get(&tile).cloned().unwrap_or_default()
+    }
+
+    pub fn get_baseline_mut(&mut self, tile: TileID) -> &mut ParticipationStats {
+        self.baselines.entry(tile).or_default()
+    }
+
+    pub fn draw_agents(&mut self, count: usize, rng: &mut StdRng) -> Vec<Agent> {
+        (0..count).map(|_| self.generate_agent(rng)).collect()
+    }
+
+    /// Draws candidates for tile membership. Every candidate is a former member with probability 1/2
+    /// (while there are former members left), otherwise it is a newly generated agent.
+    pub fn draw_candidates(&mut self, count: usize, rng: &mut StdRng) -> Vec<Agent> {
+        let former_member_count = (0..count).filter(|_| rng.gen_bool(0.5)).count();
+        let mut candidates = self.former_members.values()
+            .cloned()
+            .choose_multiple(rng, former_member_count);
+        while candidates.len() < count {
+            candidates.push(self.generate_agent(rng));
+        }
+        candidates
+    }
+}
+
+impl ParticipationStats {
+    pub fn record(&mut self, before: &Agent, after: &Agent) {
+        self.participations += 1;
+        add_resource_changes(&mut self.resource_changes, &get_resource_changes(&before.resources, &after.resources));
+        self.utility_change += after.get_utility() - before.get_utility();
+    }
+
+    pub fn add(&mut self, other: &ParticipationStats) {
+        self.participations += other.participations;
+        add_resource_changes(&mut self.resource_changes, &other.resource_changes);
+        self.utility_change += other.utility_change;
+    }
+
+    pub fn participations(&self) -> usize {
+        self.participations
+    }
+
+    pub fn average_utility_change(&self) -> f64 {
+        self.utility_change / usize::max(self.participations, 1) as f64
+    }
+
+    pub fn summary(&self) -> String {
+        let participations = usize::max(self.participations, 1) as f64;
+        let average_resource_changes = self.resource_changes.iter()
+            .map(|(resource, change)| (resource.clone(), *change as f64 / participations))
+            .collect::<BTreeMap<AnyResource, f64>>();
+        format!(
+            "Participations: {}, average resource changes per participation: {:?}, average utility change per participation: {:.4}",
+            self.participations, average_resource_changes, self.average_utility_change()
+        )
+    }
+}
@@ -0,0 +1,92 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+/// Frequency of games of a provider (see fundamental frequency heuristic in docs/runtime.md).
+/// Rates are in games per tick, periods are in ticks and should not be rational fractions of a tick.
+#[derive(Clone, Copy, Debug)]
+pub enum AnyFrequency {
+    Poisson(f64), // Mean rate. Games arrive independently of each other
+    JitteredPeriod(f64, f64), // Period and relative jitter. Every game is shifted from the regular grid within ±jitter * period
+    SpreadSpectrum(f64, f64), // Mean period and relative spread. Every interval between games is uniform within period * (1 ± spread)
+}
+
+/// Arrival times of games of a single provider. Phase of every schedule is random, so providers with
+/// equal frequencies do not emit games on the same ticks.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    frequency: AnyFrequency,
+    phase: f64, // Position of the regular grid of jittered period
+    arrivals: usize,
+    next_arrival: f64, // Time of the next game, in ticks
+}
+
+impl Schedule {
+    pub fn new(frequency: AnyFrequency, rng: &mut StdRng) -> Schedule {
+        let phase = match frequency {
+            AnyFrequency::Poisson(rate) => exponential_interval(rate, rng),
+            AnyFrequency::JitteredPeriod(period, _) |
+            AnyFrequency::SpreadSpectrum(period, _) => rng.gen_range(0.0..period),
+        };
+        Schedule {frequency, phase, arrivals: 0, next_arrival: phase}
+    }
+
+    /// Returns the number of games arriving during the tick.
+    pub fn count_games(&mut self, tick: usize, rng: &mut StdRng) -> usize {
+        let mut count = 0;
+        while self.next_arrival < (tick + 1) as f64 {
+            count += 1;
+            self.arrivals += 1;
+            self.next_arrival = match self.frequency {
+                AnyFrequency::Poisson(rate) => self.next_arrival + exponential_interval(rate, rng),
+                AnyFrequency::JitteredPeriod(period, jitter) => {
+                    let shift = if jitter > 0.0 {rng.gen_range(-jitter..jitter) * period} else {0.0};
+                    f64::max(self.phase + self.arrivals as f64 * period + shift, self.next_arrival) // Games do not arrive out of order
+                }
+                AnyFrequency::SpreadSpectrum(period, spread) => {
+                    let factor = if spread > 0.0 {rng.gen_range(1.0 - spread..1.0 + spread)} else {1.0};
+                    self.next_arrival + period * factor
+                }
+            };
+        }
+        count
+    }
+}
+
+/// Interval between events of a Poisson process, sampled by inverting the exponential distribution.
+fn exponential_interval(rate: f64, rng: &mut StdRng) -> f64 {
+    -f64::ln(1.0 - rng.gen::<f64>()) / rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const TICKS: usize = 10000;
+
+    fn mean_rate(frequency: AnyFrequency) -> f64 {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut schedule = Schedule::new(frequency, &mut rng);
+        let games = (0..TICKS).map(|tick| schedule.count_games(tick, &mut rng)).sum::<usize>();
+        games as f64 / TICKS as f64
+    }
+
+    #[test]
+    fn poisson_schedule_has_mean_rate() {
+        assert!((mean_rate(AnyFrequency::Poisson(0.3)) - 0.3).abs() < 0.02);
+        assert!((mean_rate(AnyFrequency::Poisson(2.5)) - 2.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn periodic_schedules_have_rate_of_inverse_period() {
+        assert!((mean_rate(AnyFrequency::JitteredPeriod(3.7, 0.5)) - 1.0 / 3.7).abs() < 0.001);
+        assert!((mean_rate(AnyFrequency::SpreadSpectrum(0.4, 0.5)) - 2.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn jittered_period_without_jitter_is_regular() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut schedule = Schedule::new(AnyFrequency::JitteredPeriod(0.5, 0.0), &mut rng);
+        assert!((0..100).all(|tick| schedule.count_games(tick, &mut rng) == 2));
+    }
+}
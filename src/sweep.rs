@@ -0,0 +1,243 @@
+use std::fs;
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use toml::Value;
+
+use super::templates::read_string;
+
+const SEED_KEY: &str = "seed"; // Model seed of every run is drawn from the sweep seed, unless it is set or swept explicitly
+
+/// Parameter sweep (see example in sweeps/default.toml). Every run gets the base config with some of its values
+/// replaced and is executed as a separate process of the model, so that nothing is shared between runs.
+/// Keys are dotted paths within [[Configs]], with indices for arrays, for example "tiles.1.agent_count"
+/// or "actions.mint.payout". Run it with --sweep <spec>.
+#[derive(Clone, Debug)]
+pub struct SweepSpec {
+    base_config: String,
+    output: String,
+    samples: usize,
+    seed: u64, // Seed of perturbations and of model seeds of runs
+    parallel_runs: usize,
+    set: Vec<(String, Value)>,
+    grid: Vec<(String, Vec<Value>)>,
+    perturbations: Vec<(String, f64)>, // Keys and relative spreads
+}
+
+#[derive(Clone, Debug)]
+struct Run {
+    directory: String,
+    values: Vec<(String, Value)>, // Values of grid and perturbation keys and the model seed
+}
+
+pub fn run_sweep(spec_pathname: &str) {
+    let spec_value: Value = fs::read_to_string(spec_pathname).unwrap_or_else(|e| panic!("Sweep spec {} is not readable: {}", spec_pathname, e))
+        .parse().unwrap_or_else(|e| panic!("Sweep spec {} is not valid TOML: {}", spec_pathname, e));
+    let spec = read_sweep_spec(&spec_value).unwrap_or_else(|e| panic!("Sweep spec {} is invalid: {}", spec_pathname, e));
+    let base_config: Value = fs::read_to_string(&spec.base_config).unwrap().parse().unwrap();
+
+    let runs = prepare_runs(&spec, &base_config).unwrap_or_else(|e| panic!("Sweep spec {} is invalid: {}", spec_pathname, e));
+    println!("Sweep of {} runs, see {}", runs.len(), spec.output);
+
+    let next_run = AtomicUsize::new(0);
+    let statuses = Mutex::new(vec![false; runs.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..spec.parallel_runs {
+            scope.spawn(|| {
+                loop {
+                    let index = next_run.fetch_add(1, Ordering::SeqCst);
+                    let Some(run) = runs.get(index) else {break};
+                    let succeeded = execute_run(run);
+                    println!("Run {} {}", run.directory, if succeeded {"finished"} else {"failed"});
+                    statuses.lock().unwrap()[index] = succeeded;
+                }
+            });
+        }
+    });
+
+    write_summary(&spec, &runs, &statuses.into_inner().unwrap());
+    println!("See sweep summary: {}/summary.csv", spec.output);
+}
+
+fn read_sweep_spec(entry: &Value) -> Result<SweepSpec, String> {
+    let read_count = |searched_var: &str| match entry.get(searched_var) {
+        None => Ok(1),
+        Some(value) => value.as_integer().filter(|count| *count > 0).map(|count| count as usize)
+            .ok_or(format!("{} should be a positive integer", searched_var)),
+    };
+
+    let set = match entry.get("set") {
+        None => vec![],
+        Some(set) => set.as_table().ok_or("set should be a table")?
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    };
+
+    let mut grid = vec![];
+    for dimension in entry.get("grid").and_then(Value::as_array).unwrap_or(&vec![]) {
+        let key = read_string(dimension, "key")?;
+        let values = dimension.get("values").and_then(Value::as_array).filter(|values| !values.is_empty())
+            .ok_or(format!("Grid of {} should have non-empty values", key))?;
+        grid.push((key, values.clone()));
+    }
+
+    let mut perturbations = vec![];
+    for perturbation in entry.get("perturbations").and_then(Value::as_array).unwrap_or(&vec![]) {
+        let key = read_string(perturbation, "key")?;
+        let spread = perturbation.get("spread").and_then(Value::as_float).filter(|spread| (0.0..1.0).contains(spread))
+            .ok_or(format!("Perturbation of {} should have spread within [0, 1)", key))?;
+        perturbations.push((key, spread));
+    }
+
+    Ok(SweepSpec {
+        base_config: read_string(entry, "base_config")?,
+        output: read_string(entry, "output")?,
+        samples: read_count("samples")?,
+        seed: entry.get("seed").and_then(Value::as_integer).unwrap_or(0) as u64,
+        parallel_runs: read_count("parallel_runs")?,
+        set,
+        grid,
+        perturbations,
+    })
+}
+
+/// Writes configs of all runs into their directories. Runs are ordered by grid combinations, then by samples.
+fn prepare_runs(spec: &SweepSpec, base_config: &Value) -> Result<Vec<Run>, String> {
+    let mut rng = StdRng::seed_from_u64(spec.seed);
+    let mut combinations: Vec<Vec<(String, Value)>> = vec![vec![]];
+    for (key, values) in &spec.grid {
+        combinations = combinations.into_iter()
+            .flat_map(|combination| values.iter().map(move |value| {
+                let mut combination = combination.clone();
+                combination.push((key.clone(), value.clone()));
+                combination
+            }))
+            .collect();
+    }
+
+    let mut runs = vec![];
+    for combination in combinations {
+        for _ in 0..spec.samples {
+            let mut config = base_config.clone();
+            let configs = config.get_mut("Configs").and_then(Value::as_array_mut).and_then(|configs| configs.first_mut())
+                .ok_or("Base config has no Configs")?;
+            for (key, value) in spec.set.iter().chain(&combination) {
+                *get_value_mut(configs, key)? = value.clone();
+            }
+            let mut values = combination.clone();
+            for (key, spread) in &spec.perturbations {
+                let target = get_value_mut(configs, key)?;
+                let factor = if *spread > 0.0 {rng.gen_range(1.0 - spread..1.0 + spread)} else {1.0};
+                *target = match target {
+                    Value::Integer(value) => Value::Integer((*value as f64 * factor).round() as i64),
+                    Value::Float(value) => Value::Float(*value * factor),
+                    _ => return Err(format!("Perturbed value {} is not a number", key)),
+                };
+                values.push((key.clone(), target.clone()));
+            }
+            if !spec.set.iter().chain(&combination).any(|(key, _)| key == SEED_KEY) {
+                let seed = get_value_mut(configs, SEED_KEY)?;
+                *seed = Value::Integer(rng.gen_range(0..i64::MAX)); // Samples differ by model seed as well as by perturbations
+                values.push((SEED_KEY.to_string(), seed.clone()));
+            }
+
+            let directory = format!("{}/run_{:03}", spec.output, runs.len());
+            fs::create_dir_all(&directory).unwrap();
+            fs::write(format!("{}/run_config.toml", directory), toml::to_string(&config).unwrap()).unwrap(); // Not named config.toml, so it is not found as config of the model
+            runs.push(Run {directory, values});
+        }
+    }
+    Ok(runs)
+}
+
+/// Returns the value at the dotted path. All keys should exist in the base config.
+fn get_value_mut<'a>(entry: &'a mut Value, key: &str) -> Result<&'a mut Value, String> {
+    let mut value = entry;
+    for segment in key.split('.') {
+        value = match value {
+            Value::Array(array) => segment.parse::<usize>().ok().and_then(|index| array.get_mut(index)),
+            Value::Table(table) => table.get_mut(segment),
+            _ => None,
+        }.ok_or(format!("Config has no value {}", key))?;
+    }
+    Ok(value)
+}
+
+/// Executes the model with config of the run. Output of the model is written to run.log of the run.
+fn execute_run(run: &Run) -> bool {
+    let output = Command::new(std::env::current_exe().unwrap())
+        .arg("--config").arg(format!("{}/run_config.toml", run.directory))
+        .arg("--output").arg(&run.directory)
+        .output()
+        .unwrap();
+    let log = [output.stdout, output.stderr].concat();
+    fs::write(format!("{}/run.log", run.directory), log).unwrap();
+    output.status.success()
+}
+
+/// Summary has a row per run with swept values and final metrics of the run. Failed runs have no metrics.
+fn write_summary(spec: &SweepSpec, runs: &[Run], statuses: &[bool]) {
+    let read_metrics = |run: &Run| fs::read_to_string(format!("{}/final_metrics.csv", run.directory)).ok()
+        .and_then(|metrics| {
+            let mut lines = metrics.lines().map(String::from);
+            Some((lines.next()?, lines.next()?))
+        });
+    let metric_names = runs.iter().find_map(read_metrics).map_or(String::new(), |(names, _)| names);
+    let keys = runs.first().map_or(vec![], |run| run.values.iter().map(|(key, _)| key.clone()).collect());
+
+    let mut summary = format!("run,status,{}{}{}\n", keys.join(","), if keys.is_empty() {""} else {","}, metric_names);
+    for (run, succeeded) in runs.iter().zip(statuses) {
+        let values = run.values.iter()
+            .map(|(_, value)| csv_field(&value.as_str().map_or(value.to_string(), String::from)))
+            .collect::<Vec<String>>();
+        let metrics = if *succeeded {read_metrics(run).map_or(String::new(), |(_, values)| values)} else {String::new()};
+        summary.push_str(&format!(
+            "{},{},{}{}{}\n",
+            run.directory, if *succeeded {"finished"} else {"failed"}, values.join(","), if values.is_empty() {""} else {","}, metrics
+        ));
+    }
+    fs::write(format!("{}/summary.csv", spec.output), summary).unwrap();
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_configs() -> Value {
+        r#"
+            seed = 1
+            tiles = [{ agent_count = 10 }, { agent_count = 20 }]
+            actions = { mint = { payout = 20 } }
+        "#.parse().unwrap()
+    }
+
+    #[test]
+    fn dotted_paths_reach_tables_and_arrays() {
+        let mut configs = get_configs();
+        *get_value_mut(&mut configs, "tiles.1.agent_count").unwrap() = Value::Integer(30);
+        *get_value_mut(&mut configs, "actions.mint.payout").unwrap() = Value::Float(2.5);
+
+        assert_eq!(configs["tiles"][0]["agent_count"], Value::Integer(10));
+        assert_eq!(configs["tiles"][1]["agent_count"], Value::Integer(30));
+        assert_eq!(configs["actions"]["mint"]["payout"], Value::Float(2.5));
+        assert_eq!(get_value_mut(&mut configs, "seed").unwrap(), &Value::Integer(1));
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        let mut configs = get_configs();
+        for key in ["tiles.2.agent_count", "tiles.first.agent_count", "actions.burn", "seed.value", "tiles.0.agent_count.0"] {
+            assert_eq!(get_value_mut(&mut configs, key), Err(format!("Config has no value {}", key)));
+        }
+    }
+}
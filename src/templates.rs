@@ -0,0 +1,286 @@
+use std::fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::Value;
+use walkdir::WalkDir;
+
+use super::*;
+
+/// Game templates are TOML files that describe roles, options of every role and the full matrix of outcomes.
+/// Every role of a template game is played by exactly one agent.
+/// Games are resolved simultaneously unless template sets resolution = "sequential".
+/// Agents are offered roles in uniformly random order unless template sets assignment to "weighted_by_resources",
+/// "weighted_by_reputation" or "round_robin".
+/// Roles are played by agents of the tile of the game unless role sets source = "reservoir" or source = "other_tile" with tile = N.
+/// Players are drawn from a drainable pool unless role sets pool = "non_drainable" or pool = "initiator".
+/// Templates are named after their files without extension. Example of a template:
+///
+/// resolution = "simultaneous"
+///
+/// [[roles]]
+/// name = "first_hunter"
+/// options = ["stag", "hare"]
+///
+/// [[outcomes]]
+/// choices = { first_hunter = "stag", second_hunter = "stag" }
+/// resource_changes = { first_hunter = { Coins = 4 }, second_hunter = { Coins = 4 } }
+/// reputation_changes = { first_hunter = { second_hunter = 0.1 }, second_hunter = { first_hunter = 0.1 } }
+pub fn read_game_templates(directory: &str) -> BTreeMap<String, Game> {
+    if !Path::new(directory).is_dir() {
+        return BTreeMap::new();
+    }
+
+    let mut template_files: Vec<PathBuf> = WalkDir::new(directory)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry|
+            entry.file_type().is_file() &&
+            entry.file_name().to_string_lossy().ends_with(".toml"))
+        .map(|entry| entry.into_path())
+        .collect();
+    template_files.sort(); // Order of templates should not depend on the file system
+
+    template_files.iter()
+        .map(|file| {
+            println!("Game template {:?} found", file);
+            let name = file.file_stem().unwrap().to_string_lossy().to_string();
+            let template: Value = fs::read_to_string(file).unwrap().parse().unwrap();
+            let game = read_game_template(&name, &template).unwrap_or_else(|e| panic!("Game template {:?} is invalid: {}", file, e));
+            (name, game)
+        })
+        .collect()
+}
+
+fn read_game_template(name: &str, template: &Value) -> Result<Game, String> {
+    let role_entries = template.get("roles").and_then(Value::as_array).ok_or("No roles found")?;
+    let mut roles: BTreeMap<AnyRole, RoleDescription> = BTreeMap::new();
+
+    for entry in role_entries {
+        let name = read_string(entry, "name")?;
+        let options = entry.get("options").and_then(Value::as_array).ok_or(format!("No options for role {}", name))?
+            .iter()
+            .map(|option| option.as_str().map(String::from).ok_or(format!("Option of role {} is not a string", name)))
+            .collect::<Result<Vec<String>, String>>()?;
+        if options.is_empty() {
+            return Err(format!("Role {} has no options", name));
+        }
+
+        let pool = read_pool(entry).map_err(|e| format!("Pool of role {} is invalid: {}", name, e))?;
+
+        let description = RoleDescription {
+            uniqueness: AnyUniqueness::RequiredMultipletRole(1, 1),
+            transformer: |actions| actions,
+            options,
+            pool,
+        };
+        if roles.insert(AnyRole::TemplateRole(name.clone()), description).is_some() {
+            return Err(format!("Role {} is described twice", name));
+        }
+    }
+
+    let outcome_entries = template.get("outcomes").and_then(Value::as_array).ok_or("No outcomes found")?;
+    let mut outcomes: OutcomeMatrix = BTreeMap::new();
+
+    for entry in outcome_entries {
+        let choices = entry.get("choices").ok_or("choices table not found")?;
+        let choices = read_role_table(choices, &roles)?.into_iter()
+            .map(|(role, option)| {
+                let option = option.as_str().ok_or(format!("Choice of role {:?} is not a string", role))?.to_string();
+                if !roles[&role].options.contains(&option) {
+                    return Err(format!("Role {:?} has no option {}", role, option));
+                }
+                Ok((role, option))
+            })
+            .collect::<Result<BTreeMap<AnyRole, String>, String>>()?;
+        if choices.len() != roles.len() {
+            return Err(format!("Outcome {:?} does not specify choices of all roles", choices));
+        }
+
+        let mut outcome = Outcome::default();
+        if let Some(resource_changes) = entry.get("resource_changes") {
+            for (role, changes) in read_role_table(resource_changes, &roles)? {
+                outcome.resource_changes.insert(role, read_resource_changes(&changes)?);
+            }
+        }
+        if let Some(reputation_changes) = entry.get("reputation_changes") {
+            for (role, changes) in read_role_table(reputation_changes, &roles)? {
+                let changes = read_role_table(&changes, &roles)?
+                    .into_iter()
+                    .map(|(target, change)| {
+                        let change = change.as_float().ok_or(format!("Reputation change of {:?} is not a float", target))?;
+                        Ok((target, change))
+                    })
+                    .collect::<Result<BTreeMap<AnyRole, f64>, String>>()?;
+                outcome.reputation_changes.insert(role, changes);
+            }
+        }
+
+        if outcomes.insert(choices.clone(), outcome).is_some() {
+            return Err(format!("Outcome {:?} is described twice", choices));
+        }
+    }
+
+    let combination_count = roles.values().map(|description| description.options.len()).product::<usize>();
+    if outcomes.len() != combination_count {
+        return Err(format!("Outcome matrix has {} of {} combinations", outcomes.len(), combination_count));
+    }
+
+    let resolution = match template.get("resolution").map(Value::as_str) {
+        None | Some(Some("simultaneous")) => AnyResolution::Simultaneous,
+        Some(Some("sequential")) => AnyResolution::Sequential,
+        Some(other) => return Err(format!("Unknown resolution: {:?}", other)),
+    };
+
+    let assignment = match template.get("assignment").map(Value::as_str) {
+        None | Some(Some("uniform_random")) => AnyAssignment::UniformRandom,
+        Some(Some("weighted_by_resources")) => AnyAssignment::WeightedByResources,
+        Some(Some("weighted_by_reputation")) => AnyAssignment::WeightedByReputation,
+        Some(Some("round_robin")) => AnyAssignment::RoundRobin,
+        Some(other) => return Err(format!("Unknown assignment: {:?}", other)),
+    };
+
+    Ok(Game {name: name.to_string(), roles, consequent_game: None, outcomes: Some(outcomes), resolution, tile: 0, assignment, action_params: BTreeMap::new()}) // Tile is set by the provider
+}
+
+/// Reads parameters of actions, for example { mint = { payout = 20 } }. Integer values are read as floats.
+pub fn read_action_params(entry: &Value) -> Result<BTreeMap<String, ActionParams>, String> {
+    let mut action_params = BTreeMap::new();
+    for (action, params) in entry.as_table().ok_or("actions should be a table of actions")? {
+        let params = params.as_table().ok_or(format!("Parameters of action {} should be a table", action))?
+            .iter()
+            .map(|(param, value)| {
+                let value = value.as_float().or(value.as_integer().map(|value| value as f64))
+                    .ok_or(format!("Parameter {} of action {} is not a number", param, action))?;
+                Ok((param.clone(), value))
+            })
+            .collect::<Result<ActionParams, String>>()?;
+        action_params.insert(action.clone(), params);
+    }
+    Ok(action_params)
+}
+
+/// Reads optional source, tile and pool keys that describe the pool players of a role are drawn from.
+pub fn read_pool(entry: &Value) -> Result<PoolDescription, String> {
+    let source = match entry.get("source").map(Value::as_str) {
+        None | Some(Some("tile")) => AnyAgentSource::Tile,
+        Some(Some("reservoir")) => AnyAgentSource::Reservoir,
+        Some(Some("other_tile")) => {
+            let tile = entry.get("tile").and_then(Value::as_integer).ok_or("other_tile source requires tile index")?;
+            AnyAgentSource::OtherTile(tile as TileID)
+        }
+        Some(other) => return Err(format!("Unknown source: {:?}", other)),
+    };
+    match entry.get("pool").map(Value::as_str) {
+        None | Some(Some("drainable")) => Ok(PoolDescription::drainable(source)),
+        Some(Some("non_drainable")) => Ok(PoolDescription::non_drainable(source)),
+        Some(Some("initiator")) => Ok(PoolDescription::initiator(source)),
+        Some(other) => Err(format!("Unknown pool: {:?}", other)),
+    }
+}
+
+pub fn read_string(entry: &Value, searched_var: &str) -> Result<String, String> {
+    entry.get(searched_var)
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or(format!("{} variable not found", searched_var))
+}
+
+fn read_role_table(value: &Value, roles: &BTreeMap<AnyRole, RoleDescription>) -> Result<Vec<(AnyRole, Value)>, String> {
+    let table = value.as_table().ok_or(format!("{} is not a table of roles", value))?;
+    table.iter()
+        .map(|(name, value)| {
+            let role = AnyRole::TemplateRole(name.clone());
+            if !roles.contains_key(&role) {
+                return Err(format!("No description for this role: {}", name));
+            }
+            Ok((role, value.clone()))
+        })
+        .collect()
+}
+
+fn read_resource_changes(changes: &Value) -> Result<ResourceChanges, String> {
+    let table = changes.as_table().ok_or("Resource changes should be a table")?;
+    table.iter()
+        .map(|(name, change)| {
+            let resource = AnyResource::from_str(name).map_err(|_| format!("Unknown resource: {}", name))?;
+            let change = change.as_integer().ok_or(format!("Change of {} is not an integer", name))?;
+            Ok((resource, change))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementation::define_test_resources;
+
+    const ROLES: &str = r#"
+        [[roles]]
+        name = "first"
+        options = ["stay", "leave"]
+
+        [[roles]]
+        name = "second"
+        options = ["stay"]
+    "#;
+
+    fn read(outcomes: &str) -> Result<Game, String> {
+        define_test_resources();
+        let template: Value = format!("{}{}", ROLES, outcomes).parse().unwrap();
+        read_game_template("test", &template)
+    }
+
+    #[test]
+    fn complete_outcome_matrix_is_read() {
+        let game = read(r#"
+            [[outcomes]]
+            choices = { first = "stay", second = "stay" }
+            resource_changes = { first = { Coins = 1 } }
+
+            [[outcomes]]
+            choices = { first = "leave", second = "stay" }
+        "#).unwrap();
+
+        assert_eq!(game.name, "test");
+        assert_eq!(game.roles.len(), 2);
+        assert_eq!(game.outcomes.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn incomplete_outcome_matrix_is_rejected() {
+        let result = read(r#"
+            [[outcomes]]
+            choices = { first = "stay", second = "stay" }
+        "#);
+
+        assert_eq!(result.err().unwrap(), "Outcome matrix has 1 of 2 combinations");
+    }
+
+    #[test]
+    fn duplicate_outcome_is_rejected() {
+        let result = read(r#"
+            [[outcomes]]
+            choices = { first = "stay", second = "stay" }
+
+            [[outcomes]]
+            choices = { first = "stay", second = "stay" }
+            resource_changes = { first = { Coins = 1 } }
+        "#);
+
+        assert!(result.err().unwrap().ends_with("is described twice"));
+    }
+
+    #[test]
+    fn outcome_without_choices_of_all_roles_is_rejected() {
+        let result = read(r#"
+            [[outcomes]]
+            choices = { first = "stay" }
+
+            [[outcomes]]
+            choices = { first = "leave", second = "stay" }
+        "#);
+
+        assert!(result.err().unwrap().ends_with("does not specify choices of all roles"));
+    }
+}